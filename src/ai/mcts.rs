@@ -0,0 +1,341 @@
+//! Monte Carlo tree search with UCT selection and random playouts.
+//! The tree is kept between moves, so the search carries on from wherever the game went.
+
+use std::time::{Duration, Instant};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{bitboard::BitGameState, Action, Game, GameOutcome, GameState, Move, Player};
+
+use super::Agent;
+
+/// How long to search for each move
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Budget {
+    /// Run this many playouts
+    Iterations(u32),
+    /// Run playouts until this much time has passed
+    Time(Duration),
+}
+
+/// Search results for one move from the root
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MoveStats {
+    pub mov: Move,
+    /// Number of playouts through this move
+    pub visits: u32,
+    /// Average reward of those playouts for the player making the move. A draw is worth
+    /// one share among the players, e.g. 0.5 in a two player game.
+    pub win_rate: f64,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    state: GameState,
+    /// The move leading here from the parent, if any
+    mov: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// Legal moves without a child node yet
+    untried: Vec<Move>,
+    visits: u32,
+    /// Total reward for the player who made `mov`
+    reward: f64,
+}
+
+impl Node {
+    fn new(state: GameState, mov: Option<Move>, parent: Option<usize>) -> Self {
+        Self {
+            state,
+            mov,
+            parent,
+            children: vec![],
+            untried: state.legal_moves().to_vec(),
+            visits: 0,
+            reward: 0.0,
+        }
+    }
+
+    /// The player whose move led to this node
+    fn mover(&self) -> Player {
+        let state = &self.state;
+        state.players()[(state.next_to_play_idx() + state.num_players() - 1) % state.num_players()]
+    }
+}
+
+/// A Monte Carlo tree search agent
+#[derive(Clone, Debug)]
+pub struct Mcts {
+    /// Exploration constant for UCT. Higher values try less promising moves more often.
+    pub exploration: f64,
+    /// How long to search for each move
+    pub budget: Budget,
+    /// The search tree, rooted at index 0
+    nodes: Vec<Node>,
+    rng: StdRng,
+}
+
+impl Mcts {
+    /// Create a searcher with the usual exploration constant of √2
+    pub fn new(budget: Budget) -> Self {
+        Self {
+            exploration: std::f64::consts::SQRT_2,
+            budget,
+            nodes: vec![],
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Use a fixed random seed, to make searches repeatable
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Search from this state within the budget, and return the most visited move.
+    /// Returns None if the game is over.
+    pub fn search(&mut self, state: &GameState) -> Option<Move> {
+        self.set_root(state);
+        if state.legal_moves().is_empty() {
+            return None;
+        }
+
+        let start = Instant::now();
+        let mut iterations = 0;
+        loop {
+            let done = match self.budget {
+                Budget::Iterations(n) => iterations >= n,
+                Budget::Time(limit) => start.elapsed() >= limit,
+            };
+            if done && iterations > 0 {
+                break;
+            }
+            self.iterate();
+            iterations += 1;
+        }
+
+        self.root_stats()
+            .into_iter()
+            .max_by_key(|stats| stats.visits)
+            .map(|stats| stats.mov)
+    }
+
+    /// Visit counts and win rates for each move from the root, in the order they were first tried
+    pub fn root_stats(&self) -> Vec<MoveStats> {
+        let Some(root) = self.nodes.first() else {
+            return vec![];
+        };
+        root.children
+            .iter()
+            .map(|&idx| {
+                let node = &self.nodes[idx];
+                MoveStats {
+                    mov: node.mov.unwrap(),
+                    visits: node.visits,
+                    win_rate: node.reward / node.visits.max(1) as f64,
+                }
+            })
+            .collect()
+    }
+
+    /// Number of playouts made through the root, including those from earlier searches
+    pub fn root_visits(&self) -> u32 {
+        self.nodes.first().map_or(0, |root| root.visits)
+    }
+
+    /// Make the given state the root, keeping its subtree if it is within a round of the old root
+    fn set_root(&mut self, state: &GameState) {
+        let mut frontier = vec![0];
+        for _ in 0..=state.num_players() {
+            if let Some(&idx) = frontier
+                .iter()
+                .find(|&&idx| idx < self.nodes.len() && self.nodes[idx].state == *state)
+            {
+                self.reroot(idx);
+                return;
+            }
+            frontier = frontier
+                .iter()
+                .filter(|&&idx| idx < self.nodes.len())
+                .flat_map(|&idx| self.nodes[idx].children.iter().copied())
+                .collect();
+        }
+
+        self.nodes = vec![Node::new(*state, None, None)];
+    }
+
+    /// Keep only the subtree below `root`, moving it to the front
+    fn reroot(&mut self, root: usize) {
+        let old = std::mem::take(&mut self.nodes);
+        let mut stack = vec![(root, None)];
+        while let Some((old_idx, parent)) = stack.pop() {
+            let new_idx = self.nodes.len();
+            let node = &old[old_idx];
+            self.nodes.push(Node {
+                parent,
+                children: vec![],
+                ..node.clone()
+            });
+            if let Some(parent) = parent {
+                self.nodes[parent].children.push(new_idx);
+            }
+            stack.extend(node.children.iter().rev().map(|&c| (c, Some(new_idx))));
+        }
+        self.nodes[0].mov = None;
+    }
+
+    /// Child of `idx` with the highest upper confidence bound
+    fn select_child(&self, idx: usize) -> usize {
+        let log_visits = (self.nodes[idx].visits as f64).ln();
+        let uct = |&child: &usize| {
+            let node = &self.nodes[child];
+            let visits = node.visits as f64;
+            node.reward / visits + self.exploration * (log_visits / visits).sqrt()
+        };
+        *self.nodes[idx]
+            .children
+            .iter()
+            .max_by(|a, b| uct(a).total_cmp(&uct(b)))
+            .unwrap()
+    }
+
+    /// One round of selection, expansion, playout and backpropagation
+    fn iterate(&mut self) {
+        // Select
+        let mut idx = 0;
+        while self.nodes[idx].untried.is_empty() && !self.nodes[idx].children.is_empty() {
+            idx = self.select_child(idx);
+        }
+
+        // Expand
+        let untried = &mut self.nodes[idx].untried;
+        if !untried.is_empty() {
+            let mov = untried.swap_remove(self.rng.gen_range(0..untried.len()));
+            let state = self.nodes[idx].state.apply_move(mov);
+            self.nodes.push(Node::new(state, Some(mov), Some(idx)));
+            let child = self.nodes.len() - 1;
+            self.nodes[idx].children.push(child);
+            idx = child;
+        }
+
+        // Play out
+        let state = &self.nodes[idx].state;
+        let outcome = playout(BitGameState::from(state), &mut self.rng);
+        let draw_reward = 1.0 / state.num_players() as f64;
+
+        // Backpropagate
+        let mut next = Some(idx);
+        while let Some(idx) = next {
+            let node = &mut self.nodes[idx];
+            node.visits += 1;
+            node.reward += match outcome {
+                GameOutcome::Won(winner) if winner == node.mover() => 1.0,
+                GameOutcome::Won(_) => 0.0,
+                _ => draw_reward,
+            };
+            next = node.parent;
+        }
+    }
+}
+
+/// Play random moves until the game is over
+fn playout(mut state: BitGameState, rng: &mut impl Rng) -> GameOutcome {
+    loop {
+        let count = state.count_legal_moves();
+        if count == 0 {
+            return state.outcome();
+        }
+        let mov = state.nth_legal_move(rng.gen_range(0..count)).unwrap();
+        state = state.apply_move(mov);
+    }
+}
+
+impl Agent for Mcts {
+    fn name(&self) -> &str {
+        "MCTS"
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Action> {
+        self.search(game.state()).map(Action::Play)
+    }
+
+    fn reset(&mut self) {
+        self.nodes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board_shorthand, RuleSet, EMPTY_BOARD};
+
+    #[test]
+    fn test_finds_win() {
+        // X has won boards 0 and 1 and can win board 2, which they have been sent to
+        let x_board = board_shorthand(*b"XXX-O-O--");
+        let mut superboard = [EMPTY_BOARD; 9];
+        superboard[0] = x_board;
+        superboard[1] = x_board;
+        superboard[2] = board_shorthand(*b"XX-OO----");
+        let state = GameState::new(b"XO", RuleSet::default())
+            .with_superboard(superboard)
+            .with_sent_to(Some(2));
+
+        let mut mcts = Mcts::new(Budget::Iterations(2000)).with_seed(0);
+        let win = Move {
+            superboard: None,
+            board: 2,
+        };
+        assert_eq!(mcts.search(&state), Some(win));
+
+        let stats = mcts.root_stats();
+        assert_eq!(stats.len(), 5);
+        assert_eq!(stats.iter().map(|s| s.visits).sum::<u32>(), 2000);
+        let best = stats.iter().find(|s| s.mov == win).unwrap();
+        assert_eq!(best.win_rate, 1.0);
+    }
+
+    #[test]
+    fn test_subtree_reuse() {
+        let mut mcts = Mcts::new(Budget::Iterations(1000)).with_seed(0);
+        let state = GameState::new(b"XO", RuleSet::default());
+        let mov = mcts.search(&state).unwrap();
+
+        // After our move and a reply, the search carries on from the playouts already made there
+        let state = state.apply_move(mov);
+        let reply = state.legal_moves()[0];
+        let state = state.apply_move(reply);
+        mcts.set_root(&state);
+        let reused = mcts.root_visits();
+        assert!(reused > 0);
+        mcts.search(&state).unwrap();
+        assert_eq!(mcts.root_visits(), reused + 1000);
+
+        // A state the tree never reached starts afresh
+        mcts.reset();
+        mcts.search(&state).unwrap();
+        assert_eq!(mcts.root_visits(), 1000);
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let mut mcts = Mcts::new(Budget::Time(Duration::from_millis(20)));
+        let state = GameState::new(b"XOA", RuleSet::default());
+        assert!(mcts.search(&state).is_some());
+        assert!(mcts.root_visits() > 0);
+        let over = state.with_superboard([board_shorthand(*b"XXX------"); 9]);
+        assert_eq!(mcts.search(&over), None);
+    }
+
+    #[test]
+    fn test_finished_game() {
+        // No budget is spent on a game that is already over
+        let mut mcts = Mcts::new(Budget::Time(Duration::from_secs(60)));
+        let over = GameState::new(b"XO", RuleSet::default())
+            .with_superboard([board_shorthand(*b"XXX------"); 9]);
+        let start = Instant::now();
+        assert_eq!(mcts.search(&over), None);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(mcts.root_visits(), 0);
+    }
+}
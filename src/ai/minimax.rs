@@ -0,0 +1,703 @@
+//! Depth-limited negamax search with alpha-beta pruning, and the heuristic it uses at the horizon.
+//! Every player other than the one to move is treated as a single opponent, so the search is
+//! only exact for two players.
+//!
+//! `Minimax::iterative_deepening` searches one ply deeper at a time within `SearchLimits`, so it
+//! can play to a clock. Its stop flag can be shared with another thread, e.g. a server loop
+//! which needs a move right away.
+//!
+//! The search plays on `BitGameState`, and only converts back to a `GameState` to evaluate a
+//! position at the horizon.
+//!
+//! With a transposition table (see `Minimax::with_table`), positions reached again by another
+//! move order reuse their earlier result, and the best move stored for a position is tried first
+//! in later iterations. Symmetric positions share an entry, keyed by their canonical state.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bitboard::BitGameState,
+    board_can_be_won_by, board_table,
+    single_board_solve::Value,
+    symmetry::{transform_move, INVERSES},
+    Action, BoardResult, Game, GameOutcome, GameState, Move, Player, LINES,
+};
+
+use super::{
+    transposition::{Bound, TranspositionTable},
+    Agent,
+};
+
+/// Score of a won game. Wins found sooner score higher, by the depth left to search.
+pub const WIN_SCORE: i32 = 1_000_000;
+
+/// How much each feature of a position is worth to `evaluate`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Weights {
+    /// Each sub-board won
+    pub sub_board: i32,
+    /// Each empty square on an open sub-board which would complete a line for the player
+    pub board_threat: i32,
+    /// Each open sub-board the player would win with best play, if it were their turn there
+    /// (treating the other players as one)
+    pub winning_board: i32,
+    /// Each line on the superboard with two boards counting for the player, where the third can still be won
+    pub super_threat: i32,
+    /// Holding the centre square of an open sub-board
+    pub centre_square: i32,
+    /// Holding a corner square of an open sub-board
+    pub corner_square: i32,
+    /// Extra for winning the centre sub-board
+    pub centre_board: i32,
+    /// Extra for winning a corner sub-board
+    pub corner_board: i32,
+    /// Having a free choice of sub-board, because the last move sent the player to a closed one
+    pub free_choice: i32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            sub_board: 100,
+            board_threat: 10,
+            winning_board: 20,
+            super_threat: 150,
+            centre_square: 6,
+            corner_square: 3,
+            centre_board: 40,
+            corner_board: 15,
+            free_choice: 30,
+        }
+    }
+}
+
+/// Returns the centre or corner bonus for a square (or sub-board) index
+fn position_bonus(idx: usize, centre: i32, corner: i32) -> i32 {
+    match idx {
+        4 => centre,
+        0 | 2 | 6 | 8 => corner,
+        _ => 0,
+    }
+}
+
+/// Score a position for the player to move: positive is good for them. Finished games
+/// score `WIN_SCORE`, `-WIN_SCORE` or 0.
+pub fn evaluate(state: &GameState, weights: &Weights) -> i32 {
+    match state.outcome() {
+        GameOutcome::Ongoing => evaluate_ongoing(state, weights),
+        outcome => outcome_score(outcome, state.next_to_play()),
+    }
+}
+
+/// Score of a finished game for the player `me`
+fn outcome_score(outcome: GameOutcome, me: Player) -> i32 {
+    match outcome {
+        GameOutcome::Won(winner) if winner == me => WIN_SCORE,
+        GameOutcome::Won(_) => -WIN_SCORE,
+        _ => 0,
+    }
+}
+
+/// `evaluate` for a game which is still in progress
+fn evaluate_ongoing(state: &GameState, weights: &Weights) -> i32 {
+    let me = state.next_to_play();
+    let mut score: i32 = state
+        .players()
+        .iter()
+        .map(|&player| {
+            let player_score = evaluate_player(state, player, weights);
+            if player == me {
+                player_score
+            } else {
+                -player_score
+            }
+        })
+        .sum();
+
+    if state.sent_to().is_none() && !state.is_first_move() {
+        score += weights.free_choice;
+    }
+    score
+}
+
+/// Sum of the features in `Weights` which belong to one player, ignoring whose turn it is
+fn evaluate_player(state: &GameState, player: Player, weights: &Weights) -> i32 {
+    let results: [BoardResult; 9] = std::array::from_fn(|idx| state.board_result(idx));
+    let mut score = 0;
+
+    for (board_idx, board) in state.superboard().iter().enumerate() {
+        match results[board_idx] {
+            BoardResult::Won(winner) if winner == player => {
+                score += weights.sub_board
+                    + position_bonus(board_idx, weights.centre_board, weights.corner_board);
+            }
+            BoardResult::Open => {
+                for (square_idx, &square) in board.iter().enumerate() {
+                    if square == Some(player) {
+                        score += position_bonus(
+                            square_idx,
+                            weights.centre_square,
+                            weights.corner_square,
+                        );
+                    }
+                }
+                let code = board_table::encode_for(board, player);
+                let info = board_table::board_info(code);
+                score += weights.board_threat * info.winning_squares[0].count_ones() as i32;
+                if board_table::board_value(code)[0].value == Value::Win {
+                    score += weights.winning_board;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let super_threats = LINES.iter().filter(|line| {
+        let counting = line
+            .iter()
+            .filter(|&&i| state.rules().counts_for(results[i], player))
+            .count();
+        let open = line.iter().find(|&&i| results[i] == BoardResult::Open);
+        counting == 2 && open.is_some_and(|&i| board_can_be_won_by(&state.superboard()[i], player))
+    });
+    score += weights.super_threat * super_threats.count() as i32;
+
+    score
+}
+
+/// When to stop an iterative deepening search. The first iteration always runs to completion,
+/// so a search with legal moves always has a move to return.
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    /// Deepest iteration to run, in plies. None searches until another limit is hit,
+    /// or the result can't change.
+    pub max_depth: Option<u32>,
+    /// Stop after visiting this many positions
+    pub max_nodes: Option<u64>,
+    /// Stop after this much time
+    pub max_time: Option<Duration>,
+    /// Stop as soon as this is set, e.g. from another thread when a server is asked to move now
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+impl SearchLimits {
+    /// Search to a fixed depth
+    pub fn depth(depth: u32) -> Self {
+        Self {
+            max_depth: Some(depth),
+            ..Self::default()
+        }
+    }
+
+    /// Search for a fixed time
+    pub fn time(max_time: Duration) -> Self {
+        Self {
+            max_time: Some(max_time),
+            ..Self::default()
+        }
+    }
+}
+
+/// The outcome of the last completed iteration of a search
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    /// The best move found
+    pub mov: Move,
+    /// Its score for the player to move
+    pub score: i32,
+    /// Depth of the iteration which found it
+    pub depth: u32,
+    /// Positions visited over the whole search, including any unfinished iteration
+    pub nodes: u64,
+    /// The principal variation: the expected line of play, starting with `mov`
+    pub pv: Vec<Move>,
+}
+
+/// Nodes between checks of the clock and the stop flag
+const CHECK_INTERVAL: u64 = 1024;
+
+/// A negamax searcher, which can also play as an agent
+#[derive(Clone, Debug)]
+pub struct Minimax {
+    /// When to stop searching
+    pub limits: SearchLimits,
+    /// Weights for the evaluation at the horizon
+    pub weights: Weights,
+    /// Positions visited by the last search
+    pub nodes: u64,
+    /// Results kept between positions and searches, if any
+    pub table: Option<TranspositionTable>,
+    /// When the current search must stop by, if it has a time limit
+    deadline: Option<Instant>,
+    /// Whether the current iteration may be cut short
+    can_abort: bool,
+    /// Set once the current iteration has been cut short
+    aborted: bool,
+}
+
+impl Minimax {
+    /// Create a searcher for a fixed depth with the default weights
+    pub fn new(depth: u32) -> Self {
+        Self::with_limits(SearchLimits::depth(depth))
+    }
+
+    /// Create a searcher with the given limits and the default weights
+    pub fn with_limits(limits: SearchLimits) -> Self {
+        Self {
+            limits,
+            weights: Weights::default(),
+            nodes: 0,
+            table: None,
+            deadline: None,
+            can_abort: false,
+            aborted: false,
+        }
+    }
+
+    /// Keep a transposition table with room for up to `entries` results
+    pub fn with_table(mut self, entries: usize) -> Self {
+        self.table = Some(TranspositionTable::new(entries));
+        self
+    }
+
+    /// Returns the best move from this state along with its score for the player to move,
+    /// or None if the game is over
+    pub fn search(&mut self, state: &GameState) -> Option<(Move, i32)> {
+        self.iterative_deepening(state)
+            .map(|result| (result.mov, result.score))
+    }
+
+    /// Search one ply deeper at a time until a limit is hit, trying the principal variation
+    /// from each iteration first in the next. Returns the result of the last completed iteration,
+    /// or None if the game is over.
+    pub fn iterative_deepening(&mut self, state: &GameState) -> Option<SearchResult> {
+        self.nodes = 0;
+        self.deadline = self.limits.max_time.map(|time| Instant::now() + time);
+        self.aborted = false;
+        if let Some(table) = &mut self.table {
+            table.new_search();
+        }
+
+        // No line can be longer than the number of empty squares
+        let empty_squares = state.superboard().iter().flatten().filter(|s| s.is_none());
+        let max_depth =
+            (empty_squares.count() as u32).min(self.limits.max_depth.unwrap_or(u32::MAX));
+
+        let state = BitGameState::from(state);
+        let mut result: Option<SearchResult> = None;
+        for depth in 1..=max_depth.max(1) {
+            self.can_abort = depth > 1;
+            let hint = result.as_ref().map_or(vec![], |result| result.pv.clone());
+            let mut pv = vec![];
+            let score = self.negamax(&state, depth, -WIN_SCORE * 2, WIN_SCORE * 2, &hint, &mut pv);
+            if self.aborted {
+                break;
+            }
+            let &mov = pv.first()?;
+            result = Some(SearchResult {
+                mov,
+                score,
+                depth,
+                nodes: self.nodes,
+                pv,
+            });
+
+            // A forced win or loss won't change with more depth
+            if score.abs() >= WIN_SCORE {
+                break;
+            }
+        }
+
+        result.map(|result| SearchResult {
+            nodes: self.nodes,
+            ..result
+        })
+    }
+
+    /// Returns true if the current iteration should stop now
+    fn out_of_budget(&self) -> bool {
+        if !self.can_abort {
+            return false;
+        }
+        if self.limits.max_nodes.is_some_and(|max| self.nodes >= max) {
+            return true;
+        }
+        self.nodes % CHECK_INTERVAL == 0
+            && (self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+                || self
+                    .limits
+                    .stop
+                    .as_ref()
+                    .is_some_and(|stop| stop.load(Ordering::Relaxed)))
+    }
+
+    /// Score of this state for the player to move, searching `depth` plies within the window `alpha..beta`.
+    /// `hint` is the expected line from here, whose first move is tried first. The best line found is left in `pv`.
+    fn negamax(
+        &mut self,
+        state: &BitGameState,
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+        hint: &[Move],
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        pv.clear();
+        let original_alpha = alpha;
+        if self.aborted || self.out_of_budget() {
+            self.aborted = true;
+            return 0;
+        }
+        self.nodes += 1;
+
+        let moves = state.legal_moves();
+        if moves.is_empty() || depth == 0 {
+            // Only positions still in play need the squares, for the heuristic
+            let score = match state.outcome() {
+                GameOutcome::Ongoing => evaluate_ongoing(&state.to_game_state(), &self.weights),
+                outcome => outcome_score(outcome, state.next_to_play()),
+            };
+            // Prefer quicker wins and slower losses
+            return match score {
+                WIN_SCORE => WIN_SCORE + depth as i32,
+                score if score == -WIN_SCORE => -WIN_SCORE - depth as i32,
+                score => score,
+            };
+        }
+
+        // A stored result is good enough if it was searched at least as deep, and is exact
+        // or a bound which already falls outside the window
+        let (table_key, sym) = match self.table {
+            Some(_) => {
+                let (canonical, sym) = state.canonical_symmetry();
+                (canonical.key(), sym)
+            }
+            None => (state.key(), 0),
+        };
+        let stored = self.table.as_ref().and_then(|table| {
+            let mut entry = *table.probe(table_key)?;
+            entry.best_move = entry
+                .best_move
+                .map(|mov| transform_move(INVERSES[sym], mov));
+            Some(entry)
+        });
+        if let Some(entry) = stored.filter(|entry| entry.depth >= depth) {
+            let score = shift_win_score(entry.score, depth as i32 - entry.depth as i32);
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if usable {
+                pv.extend(entry.best_move);
+                return score;
+            }
+        }
+        let stored_move = stored.and_then(|entry| entry.best_move);
+
+        // Try the expected line first, or failing that the stored best move
+        let first = hint
+            .first()
+            .copied()
+            .or(stored_move)
+            .filter(|mov| moves.contains(mov));
+        let ordered = first
+            .into_iter()
+            .chain(moves.iter().copied().filter(|&mov| Some(mov) != first));
+
+        let mut best = -WIN_SCORE * 2;
+        let mut child_pv = vec![];
+        for mov in ordered {
+            let child_hint = match hint.split_first() {
+                Some((&expected, rest)) if expected == mov => rest,
+                _ => &[],
+            };
+            let score = -self.negamax(
+                &state.apply_move(mov),
+                depth - 1,
+                -beta,
+                -alpha,
+                child_hint,
+                &mut child_pv,
+            );
+            if self.aborted {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                pv.clear();
+                pv.push(mov);
+                pv.extend_from_slice(&child_pv);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if let Some(table) = &mut self.table {
+            let bound = if best <= original_alpha {
+                Bound::Upper
+            } else if best >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            let best_move = pv.first().map(|&mov| transform_move(sym, mov));
+            table.store(table_key, depth, best, bound, best_move);
+        }
+        best
+    }
+}
+
+/// Adjust a win or loss score for searching `plies` deeper, since wins found with more depth
+/// left score higher. Other scores are unchanged.
+fn shift_win_score(score: i32, plies: i32) -> i32 {
+    if score > WIN_SCORE {
+        score + plies
+    } else if score < -WIN_SCORE {
+        score - plies
+    } else {
+        score
+    }
+}
+
+impl Agent for Minimax {
+    fn name(&self) -> &str {
+        "Minimax"
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Action> {
+        self.search(game.state()).map(|(mov, _)| Action::Play(mov))
+    }
+
+    fn reset(&mut self) {
+        if let Some(table) = &mut self.table {
+            table.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board_shorthand, RuleSet, EMPTY_BOARD};
+    use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
+
+    /// Plain negamax without pruning, to check the pruned search against
+    fn plain_negamax(state: &GameState, depth: u32, weights: &Weights) -> i32 {
+        let moves = state.legal_moves();
+        if moves.is_empty() || depth == 0 {
+            return match evaluate(state, weights) {
+                WIN_SCORE => WIN_SCORE + depth as i32,
+                score if score == -WIN_SCORE => -WIN_SCORE - depth as i32,
+                score => score,
+            };
+        }
+        moves
+            .iter()
+            .map(|&mov| -plain_negamax(&state.apply_move(mov), depth - 1, weights))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let weights = Weights::default();
+        let state = GameState::new(b"XO", RuleSet::default());
+        assert_eq!(evaluate(&state, &weights), 0);
+
+        // X takes the centre of the centre board and sends O to it
+        let state = state.apply_move(Move {
+            superboard: Some(4),
+            board: 4,
+        });
+        // With the first move there, X would win the board
+        assert_eq!(
+            evaluate(&state, &weights),
+            -weights.centre_square - weights.winning_board
+        );
+
+        // X has won a corner board and has a threat on another. O is to move with a free choice.
+        let x_board = board_shorthand(*b"XXX-O-O--");
+        let threat = board_shorthand(*b"XX-------");
+        let superboard = [
+            x_board,
+            threat,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+        ];
+        let state = GameState::new(b"XO", RuleSet::default())
+            .with_superboard(superboard)
+            .with_next_to_play(1);
+        let expected_x = weights.sub_board
+            + weights.corner_board
+            + weights.corner_square
+            + weights.board_threat
+            + weights.winning_board;
+        assert_eq!(evaluate_player(&state, b'X', &weights), expected_x);
+        assert_eq!(evaluate(&state, &weights), weights.free_choice - expected_x);
+    }
+
+    #[test]
+    fn test_finds_win() {
+        // X has won boards 0 and 1 and can win board 2, which they have been sent to
+        let x_board = board_shorthand(*b"XXX-O-O--");
+        let superboard = [
+            x_board,
+            x_board,
+            board_shorthand(*b"XX-OO----"),
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+            EMPTY_BOARD,
+        ];
+        let state = GameState::new(b"XO", RuleSet::default())
+            .with_superboard(superboard)
+            .with_sent_to(Some(2));
+
+        let weights = Weights::default();
+        assert!(
+            evaluate_player(&state, b'X', &weights) > 2 * weights.sub_board + weights.super_threat
+        );
+
+        let win = Move {
+            superboard: None,
+            board: 2,
+        };
+        for depth in 1..=3 {
+            let (mov, score) = Minimax::new(depth).search(&state).unwrap();
+            assert_eq!(mov, win);
+            assert!(score >= WIN_SCORE);
+        }
+    }
+
+    #[test]
+    fn test_pruning_matches_plain() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let weights = Weights::default();
+        for _ in 0..10 {
+            let mut state = GameState::new(b"XO", RuleSet::default());
+            for _ in 0..20 {
+                let moves = state.legal_moves();
+                let Some(&mov) = moves.choose(&mut rng) else {
+                    break;
+                };
+                state = state.apply_move(mov);
+            }
+            if state.outcome() != GameOutcome::Ongoing {
+                continue;
+            }
+
+            let mut minimax = Minimax::new(3);
+            let (_, score) = minimax.search(&state).unwrap();
+            assert_eq!(score, plain_negamax(&state, 3, &weights));
+        }
+    }
+
+    #[test]
+    fn test_iterative_deepening() {
+        let state = GameState::new(b"XO", RuleSet::default())
+            .apply_move(Move {
+                superboard: Some(4),
+                board: 4,
+            })
+            .apply_move(Move {
+                superboard: None,
+                board: 0,
+            });
+
+        let mut minimax = Minimax::new(4);
+        let result = minimax.iterative_deepening(&state).unwrap();
+        assert_eq!(result.depth, 4);
+        assert_eq!(result.pv.len(), 4);
+        assert_eq!(result.pv[0], result.mov);
+        let mut line = state;
+        for &mov in &result.pv {
+            line = line.try_apply_move(mov).unwrap();
+        }
+        // Searching the principal variation first only saves work
+        assert_eq!(result.score, plain_negamax(&state, 4, &Weights::default()));
+
+        let mut minimax = Minimax::with_limits(SearchLimits {
+            max_nodes: Some(2000),
+            ..SearchLimits::default()
+        });
+        let result = minimax.iterative_deepening(&state).unwrap();
+        assert!(result.nodes <= 2000);
+        assert!(result.depth >= 1);
+        assert!(state.legal_moves().contains(&result.mov));
+    }
+
+    #[test]
+    fn test_transposition_table() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let weights = Weights::default();
+        for _ in 0..10 {
+            let mut state = GameState::new(b"XO", RuleSet::default());
+            for _ in 0..15 {
+                let moves = state.legal_moves();
+                let Some(&mov) = moves.choose(&mut rng) else {
+                    break;
+                };
+                state = state.apply_move(mov);
+            }
+            if state.outcome() != GameOutcome::Ongoing {
+                continue;
+            }
+
+            // The table changes how much is searched, but not the result
+            let mut minimax = Minimax::new(4).with_table(1 << 16);
+            let result = minimax.iterative_deepening(&state).unwrap();
+            assert_eq!(result.score, plain_negamax(&state, 4, &weights));
+            assert!(!minimax.table.as_ref().unwrap().is_empty());
+
+            // Searching again reuses the whole tree
+            let first_nodes = result.nodes;
+            let again = minimax.iterative_deepening(&state).unwrap();
+            assert_eq!(again.score, result.score);
+            assert!(again.nodes < first_nodes);
+
+            minimax.reset();
+            assert!(minimax.table.as_ref().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_time_and_stop_limits() {
+        let state = GameState::new(b"XO", RuleSet::default());
+        let start = Instant::now();
+        let result = Minimax::with_limits(SearchLimits::time(Duration::from_millis(50)))
+            .iterative_deepening(&state)
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(result.depth >= 1);
+
+        // Stopped from another thread, as a server would
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut minimax = Minimax::with_limits(SearchLimits {
+            stop: Some(stop.clone()),
+            ..SearchLimits::default()
+        });
+        let search = std::thread::spawn(move || minimax.iterative_deepening(&state));
+        std::thread::sleep(Duration::from_millis(50));
+        stop.store(true, Ordering::Relaxed);
+        let result = search.join().unwrap().unwrap();
+        assert!(result.depth >= 1);
+    }
+}
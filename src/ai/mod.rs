@@ -1,29 +1,211 @@
-use rand::{prelude::SliceRandom, thread_rng};
+pub mod mcts;
+pub mod minimax;
+pub mod transposition;
 
-use crate::{print_game_state, successors, GameState, Move};
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{fmt_move, print_game_state, Action, Game, GameOutcome, GameState, Move, RuleSet};
 
 /// Return a random valid move, if any
 pub fn random_move(state: GameState) -> Option<Move> {
-    successors(&state).choose(&mut thread_rng()).copied()
-}
-
-/// Run a two player game, and return the resultant board when one player has no moves
-pub fn two_player_game(
-    mut x: impl FnMut(GameState) -> Option<Move>,
-    mut o: impl FnMut(GameState) -> Option<Move>,
-) -> GameState {
-    let mut state = GameState::new(b"XO");
-    loop {
-        state = match x(state) {
-            Some(mov) => state.apply_move(mov),
-            None => break state,
-        };
+    random_move_with(&state, &mut thread_rng())
+}
 
-        state = match o(state) {
-            Some(mov) => state.apply_move(mov),
-            None => break state,
-        };
+/// Return a random valid move chosen with the given generator, if any
+pub fn random_move_with(state: &GameState, rng: &mut impl Rng) -> Option<Move> {
+    let count = state.count_legal_moves();
+    if count == 0 {
+        return None;
+    }
+    state.nth_legal_move(rng.gen_range(0..count))
+}
+
+/// An agent which plays uniformly at random from a seeded generator,
+/// so the same seed makes the same moves in the same positions.
+/// The generator is ChaCha8, whose output is fixed, unlike `StdRng`'s, which may change
+/// between versions of `rand`.
+#[derive(Clone, Debug)]
+pub struct RandomAgent {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl RandomAgent {
+    /// Create an agent with the given seed
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Create an agent with a random seed, which can be read back with `seed` to replay its games
+    pub fn from_entropy() -> Self {
+        Self::new(thread_rng().gen())
+    }
+
+    /// The seed this agent was created with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Agent for RandomAgent {
+    fn name(&self) -> &str {
+        "Random"
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Action> {
+        random_move_with(game.state(), &mut self.rng).map(Action::Play)
+    }
+
+    /// Start again from the seed, so each game replays the same moves in the same positions
+    fn reset(&mut self) {
+        self.rng = ChaCha8Rng::seed_from_u64(self.seed);
+    }
+}
+
+/// A player which can take part in `play_game`.
+/// Implemented for any `FnMut(GameState) -> Option<Move>`, so move functions such as `random_move`
+/// and closures around `debug_player` are agents as they always were. Functions which need the
+/// whole game, e.g. to ask for an undo, become agents through `from_fn`.
+pub trait Agent {
+    /// A name for this player, e.g. for game records
+    fn name(&self) -> &str {
+        "Anonymous"
+    }
+
+    /// Decide what to do in the current state of the game. Returns None to stop playing.
+    fn choose_move(&mut self, game: &Game) -> Option<Action>;
+
+    /// Called after any player's move has been played, including this agent's own
+    fn notify_move(&mut self, _game: &Game, _mov: Move) {}
+
+    /// Called after moves have been taken back, by this agent or another player
+    fn notify_undo(&mut self, _game: &Game) {}
+
+    /// Called once play stops. The outcome is `Ongoing` if a player stopped early.
+    fn game_over(&mut self, _game: &Game, _outcome: GameOutcome) {}
+
+    /// Called before a new game starts, to forget anything about the last one
+    fn reset(&mut self) {}
+}
+
+impl<F: FnMut(GameState) -> Option<Move>> Agent for F {
+    fn choose_move(&mut self, game: &Game) -> Option<Action> {
+        self(*game.state()).map(Action::Play)
+    }
+}
+
+/// An agent which calls a function of the whole game. See `from_fn`.
+pub struct FromFn<F>(F);
+
+/// Turn a function which looks at the whole game, and may ask to undo, into an agent
+pub fn from_fn<F: FnMut(&Game) -> Option<Action>>(f: F) -> FromFn<F> {
+    FromFn(f)
+}
+
+impl<F: FnMut(&Game) -> Option<Action>> Agent for FromFn<F> {
+    fn choose_move(&mut self, game: &Game) -> Option<Action> {
+        (self.0)(game)
+    }
+}
+
+/// An agent with a name
+pub struct Named<A> {
+    pub name: String,
+    pub agent: A,
+}
+
+/// Give an agent a name
+pub fn named<A: Agent>(name: impl Into<String>, agent: A) -> Named<A> {
+    Named {
+        name: name.into(),
+        agent,
+    }
+}
+
+impl<A: Agent> Agent for Named<A> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Action> {
+        self.agent.choose_move(game)
     }
+
+    fn notify_move(&mut self, game: &Game, mov: Move) {
+        self.agent.notify_move(game, mov)
+    }
+
+    fn notify_undo(&mut self, game: &Game) {
+        self.agent.notify_undo(game)
+    }
+
+    fn game_over(&mut self, game: &Game, outcome: GameOutcome) {
+        self.agent.game_over(game, outcome)
+    }
+
+    fn reset(&mut self) {
+        self.agent.reset()
+    }
+}
+
+/// Run a game from the given state with one agent per player, in turn order, and return it
+/// once it is over or a player has no moves.
+/// A player asking to undo takes back their own last move along with everyone's replies.
+pub fn play_game<'a>(state: GameState, mut players: Vec<Box<dyn Agent + 'a>>) -> Game {
+    assert_eq!(
+        players.len(),
+        state.num_players(),
+        "Expected one agent per player"
+    );
+    for player in &mut players {
+        player.reset();
+    }
+
+    let mut game = Game::new(state);
+    while game.outcome() == GameOutcome::Ongoing {
+        let action = players[game.state().next_to_play_idx()].choose_move(&game);
+
+        match action {
+            Some(Action::Play(mov)) => {
+                game.play(mov)
+                    .unwrap_or_else(|e| panic!("Illegal move {}: {}", fmt_move(mov), e));
+                for player in &mut players {
+                    player.notify_move(&game, mov);
+                }
+            }
+            Some(Action::Undo) => {
+                let num_players = game.state().num_players();
+                if game.ply() >= num_players {
+                    for _ in 0..num_players {
+                        game.undo();
+                    }
+                    for player in &mut players {
+                        player.notify_undo(&game);
+                    }
+                }
+            }
+            None => break,
+        }
+    }
+
+    let outcome = game.outcome();
+    for player in &mut players {
+        player.game_over(&game, outcome);
+    }
+    game
+}
+
+/// Run a two player game, and return it once it is over or one player has no moves.
+/// A player asking to undo takes back their own last move along with their opponent's reply.
+pub fn two_player_game(x: impl Agent, o: impl Agent) -> Game {
+    play_game(
+        GameState::new(b"XO", RuleSet::default()),
+        vec![Box::new(x), Box::new(o)],
+    )
 }
 
 /// Use the given move function, but print the state
@@ -34,3 +216,149 @@ pub fn debug_player(
     print_game_state(&state, None);
     policy(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_agents<'a>(n: usize) -> Vec<Box<dyn Agent + 'a>> {
+        (0..n)
+            .map(|_| Box::new(random_move) as Box<dyn Agent>)
+            .collect()
+    }
+
+    #[test]
+    fn test_many_player_games() {
+        for players in [&b"XOA"[..], b"XOAB"] {
+            for _ in 0..20 {
+                let game = play_game(
+                    GameState::new(players, RuleSet::default()),
+                    random_agents(players.len()),
+                );
+                assert_ne!(game.outcome(), GameOutcome::Ongoing);
+
+                // Each move was made by the next player in turn
+                let mut state = *game.initial_state();
+                for (ply, &mov) in game.history().iter().enumerate() {
+                    assert_eq!(state.next_to_play(), players[ply % players.len()]);
+                    let board = mov.superboard.or(state.sent_to()).unwrap();
+                    state = state.apply_move(mov);
+                    assert_eq!(
+                        state.superboard()[board][mov.board],
+                        Some(players[ply % players.len()])
+                    );
+                }
+
+                // Only the player who just moved can have won
+                if let GameOutcome::Won(winner) = game.outcome() {
+                    let last = (game.ply() + players.len() - 1) % players.len();
+                    assert_eq!(winner, players[last]);
+                    assert_eq!(
+                        crate::is_superboard_won(state.superboard(), &RuleSet::default()),
+                        Some(winner)
+                    );
+                }
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Counts {
+        moves: usize,
+        resets: usize,
+        outcome: Option<GameOutcome>,
+    }
+
+    /// Plays randomly, keeping count of the calls it receives
+    struct Counter<'a>(&'a mut Counts);
+
+    impl Agent for Counter<'_> {
+        fn choose_move(&mut self, game: &Game) -> Option<Action> {
+            random_move(*game.state()).map(Action::Play)
+        }
+
+        fn notify_move(&mut self, game: &Game, mov: Move) {
+            assert_eq!(game.last_move(), Some(mov));
+            self.0.moves += 1;
+        }
+
+        fn game_over(&mut self, _game: &Game, outcome: GameOutcome) {
+            self.0.outcome = Some(outcome);
+        }
+
+        fn reset(&mut self) {
+            self.0.resets += 1;
+        }
+    }
+
+    #[test]
+    fn test_notifications() {
+        let (mut x, mut o) = (Counts::default(), Counts::default());
+        let game = two_player_game(named("X", Counter(&mut x)), Counter(&mut o));
+        for counts in [&x, &o] {
+            assert_eq!(counts.moves, game.ply());
+            assert_eq!(counts.resets, 1);
+            assert_eq!(counts.outcome, Some(game.outcome()));
+        }
+        assert_eq!(named("Random", random_move).name(), "Random");
+        assert_eq!(Counter(&mut x).name(), "Anonymous");
+    }
+
+    #[test]
+    fn test_seeded_random_agent() {
+        let game = |x_seed, o_seed| {
+            play_game(
+                GameState::new(b"XO", RuleSet::default()),
+                vec![
+                    Box::new(RandomAgent::new(x_seed)),
+                    Box::new(RandomAgent::new(o_seed)),
+                ],
+            )
+        };
+        let first = game(1, 2);
+        assert_eq!(game(1, 2).history(), first.history());
+        assert_ne!(game(3, 4).history(), first.history());
+
+        let agent = RandomAgent::from_entropy();
+        assert_eq!(RandomAgent::new(agent.seed()).seed(), agent.seed());
+        assert_eq!(agent.name(), "Random");
+
+        // Resetting starts again from the seed
+        let mut agent = RandomAgent::new(5);
+        let game = Game::new(GameState::new(b"XO", RuleSet::default()));
+        let moves: Vec<_> = (0..5).map(|_| agent.choose_move(&game)).collect();
+        agent.reset();
+        assert!((0..5).map(|_| agent.choose_move(&game)).eq(moves));
+    }
+
+    #[test]
+    fn test_stops_without_move() {
+        let mut moves_left = 5;
+        let quitter = move |state: GameState| {
+            moves_left -= 1;
+            (moves_left > 0).then(|| random_move(state).unwrap())
+        };
+        let mut players = random_agents(2);
+        players.push(Box::new(quitter));
+
+        let game = play_game(GameState::new(b"XOA", RuleSet::default()), players);
+        assert_eq!(game.ply(), 4 * 3 + 2);
+        assert_eq!(game.outcome(), GameOutcome::Ongoing);
+    }
+
+    #[test]
+    fn test_from_fn() {
+        // X plays, takes the move back along with O's reply, then stops
+        let mut calls = 0;
+        let undoer = from_fn(move |game: &Game| {
+            calls += 1;
+            match calls {
+                1 => random_move(*game.state()).map(Action::Play),
+                2 => Some(Action::Undo),
+                _ => None,
+            }
+        });
+        let game = two_player_game(undoer, random_move);
+        assert_eq!(game.ply(), 0);
+    }
+}
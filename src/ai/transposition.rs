@@ -0,0 +1,189 @@
+//! A fixed-size transposition table, indexed by Zobrist key, for remembering search results
+//! between positions reached by different move orders and between searches.
+//!
+//! Each slot holds one entry. A new entry replaces an old one unless the old one came from the
+//! current search and was searched deeper, so long searches aren't washed out by shallow ones,
+//! while entries left over from earlier searches give way to anything new.
+
+use crate::Move;
+
+/// How a stored score relates to the true score of the position
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Bound {
+    /// The score is exact
+    Exact,
+    /// The search failed high, so the true score is at least this
+    Lower,
+    /// The search failed low, so the true score is at most this
+    Upper,
+}
+
+/// The result of searching one position
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Entry {
+    /// Full Zobrist key of the position, to tell it apart from others sharing its slot
+    pub key: u64,
+    /// Plies searched below the position
+    pub depth: u32,
+    /// Score for the player to move
+    pub score: i32,
+    /// Whether `score` is exact or a bound
+    pub bound: Bound,
+    /// The best move found, if any, to be tried first when the position is searched again
+    pub best_move: Option<Move>,
+    /// The search which stored this entry
+    generation: u8,
+}
+
+/// A transposition table with a power of two number of slots
+#[derive(Clone, Debug)]
+pub struct TranspositionTable {
+    slots: Vec<Option<Entry>>,
+    generation: u8,
+}
+
+impl TranspositionTable {
+    /// Create a table with room for at least one and at most `entries` entries,
+    /// rounded down to a power of two
+    pub fn new(entries: usize) -> Self {
+        let size = match entries {
+            0 | 1 => 1,
+            entries => 1 << entries.ilog2(),
+        };
+        Self {
+            slots: vec![None; size],
+            generation: 0,
+        }
+    }
+
+    /// Number of slots in the table
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Number of slots in use
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    /// Returns true if nothing has been stored since the table was created or cleared
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    fn index(&self, key: u64) -> usize {
+        key as usize & (self.slots.len() - 1)
+    }
+
+    /// Look up the entry for a position, if it is still in the table
+    pub fn probe(&self, key: u64) -> Option<&Entry> {
+        self.slots[self.index(key)]
+            .as_ref()
+            .filter(|entry| entry.key == key)
+    }
+
+    /// Store a search result, unless its slot holds a deeper result from the current search
+    pub fn store(
+        &mut self,
+        key: u64,
+        depth: u32,
+        score: i32,
+        bound: Bound,
+        best_move: Option<Move>,
+    ) {
+        let generation = self.generation;
+        let index = self.index(key);
+        let slot = &mut self.slots[index];
+        let replace = match slot {
+            None => true,
+            Some(old) => old.key == key || old.generation != generation || depth >= old.depth,
+        };
+        if replace {
+            // Keep the old best move if this search didn't find one
+            let best_move = best_move.or_else(|| {
+                slot.filter(|old| old.key == key)
+                    .and_then(|old| old.best_move)
+            });
+            *slot = Some(Entry {
+                key,
+                depth,
+                score,
+                bound,
+                best_move,
+                generation,
+            });
+        }
+    }
+
+    /// Start a new search, letting everything stored so far be replaced
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Forget every entry
+    pub fn clear(&mut self) {
+        self.slots.fill(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_and_store() {
+        let mut table = TranspositionTable::new(100);
+        assert_eq!(table.capacity(), 64);
+        assert!(table.is_empty());
+
+        let mov = Move {
+            superboard: None,
+            board: 4,
+        };
+        table.store(5, 3, 10, Bound::Exact, Some(mov));
+        let entry = table.probe(5).unwrap();
+        assert_eq!(
+            (entry.depth, entry.score, entry.bound),
+            (3, 10, Bound::Exact)
+        );
+        assert_eq!(entry.best_move, Some(mov));
+
+        // Another key in the same slot is not mistaken for it
+        assert_eq!(table.probe(5 + 64), None);
+
+        // The same position is always updated, keeping its best move
+        table.store(5, 1, -4, Bound::Upper, None);
+        let entry = table.probe(5).unwrap();
+        assert_eq!(
+            (entry.depth, entry.score, entry.bound),
+            (1, -4, Bound::Upper)
+        );
+        assert_eq!(entry.best_move, Some(mov));
+
+        table.clear();
+        assert!(table.is_empty());
+        assert_eq!(table.len(), 0);
+    }
+
+    #[test]
+    fn test_replacement() {
+        let mut table = TranspositionTable::new(16);
+        table.store(1, 5, 0, Bound::Exact, None);
+
+        // A shallower result from the same search doesn't replace a deeper one...
+        table.store(17, 2, 0, Bound::Lower, None);
+        assert!(table.probe(1).is_some());
+        assert!(table.probe(17).is_none());
+
+        // ...but an equally deep one does
+        table.store(17, 5, 0, Bound::Lower, None);
+        assert!(table.probe(1).is_none());
+        assert!(table.probe(17).is_some());
+
+        // Anything replaces a result from an earlier search
+        table.new_search();
+        table.store(1, 0, 0, Bound::Exact, None);
+        assert!(table.probe(1).is_some());
+        assert_eq!(table.len(), 1);
+    }
+}
@@ -1,50 +1,81 @@
-use std::{collections::HashMap, time::Instant};
-use ultimate_ttt::{is_superboard_won, successors, GameState};
+use std::{path::PathBuf, time::Instant};
 
-fn main() {
-    let mut tree = HashMap::new();
-    let mut queue = vec![GameState::new(b"XO")];
-    let mut i = 0;
-    let mut complete_games = 0;
-    let mut o_wins = 0;
-    let mut x_wins = 0;
+use ultimate_ttt::{
+    explore::{Explorer, BUCKETS},
+    GameState, RuleSet,
+};
 
-    let start = Instant::now();
-    while let Some(state) = queue.pop() {
-        //print_superboard(&state.superboard, Some(GamePrintGuides::Superboard));
-        //println!("--------------------------------------------------");
-        if !tree.contains_key(&state) {
-            let successor_states: Vec<GameState> = successors(&state)
-                .into_iter()
-                .map(|mov| state.apply_move(mov))
-                .collect();
-            queue.extend(successor_states.iter().copied());
-            tree.insert(state, successor_states);
-        }
+/// States to expand between progress lines and checkpoints
+const DEFAULT_INTERVAL: usize = 1_000_000;
 
-        if is_superboard_won(&state.superboard).is_some() {
-            complete_games += 1;
-        }
+/// A scratch directory, removed when this is dropped, including when the run panics
+struct ScratchDir(PathBuf);
 
-        match is_superboard_won(&state.superboard) {
-            Some(b'X') => x_wins += 1,
-            Some(b'O') => o_wins += 1,
-            _ => (),
-        }
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
 
-        i += 1;
-        if i % 10_000 == 0 {
-            let elapsed = start.elapsed().as_secs_f32();
-            let rate = i as f32 / elapsed;
-            println!(
-                "{:2} / sec, idx: {}, tree: {}, complete: {}, O/X: {}/{}",
-                rate,
-                i,
-                tree.len(),
-                complete_games,
-                o_wins,
-                x_wins
+fn main() {
+    let arg = |flag: &str| std::env::args().skip_while(|arg| arg != flag).nth(1);
+    // Don't expand states this many plies in
+    let max_ply = arg("--max-ply").map(|ply| ply.parse().expect("--max-ply takes a number"));
+    // Keep the states and checkpoint in this directory, and carry on from it if it has one
+    let checkpoint = arg("--checkpoint");
+    let interval = arg("--every").map_or(DEFAULT_INTERVAL, |n| {
+        n.parse().expect("--every takes a number of states")
+    });
+
+    // Without --checkpoint the states still live on disk, but only for this run
+    let dir: PathBuf = checkpoint.clone().map_or_else(
+        || std::env::temp_dir().join(format!("explore_gametree-{}", std::process::id())),
+        Into::into,
+    );
+    let _scratch = checkpoint.is_none().then(|| ScratchDir(dir.clone()));
+    let mut explorer = if dir.join("checkpoint").exists() {
+        let explorer = Explorer::from_checkpoint(&dir)
+            .unwrap_or_else(|e| panic!("Bad checkpoint in {}: {}", dir.display(), e));
+        if explorer.max_ply != max_ply {
+            let flag = |max: Option<usize>| {
+                max.map_or("no --max-ply".into(), |max| format!("--max-ply {}", max))
+            };
+            panic!(
+                "The checkpoint in {} was started with {}, not {}",
+                dir.display(),
+                flag(explorer.max_ply),
+                flag(max_ply)
             );
         }
+        println!("Resuming from {} at ply {}", dir.display(), explorer.ply());
+        explorer
+    } else {
+        let state = GameState::new(b"XO", RuleSet::default());
+        Explorer::new(&state, max_ply, &dir)
+            .unwrap_or_else(|e| panic!("Can't explore in {}: {}", dir.display(), e))
+    };
+
+    let start = Instant::now();
+    let mut expanded = 0;
+    while !explorer.is_done() {
+        expanded += explorer
+            .step(interval)
+            .expect("Failed to read or write states");
+        let rate = expanded as f32 / start.elapsed().as_secs_f32();
+        println!(
+            "{:.0} / sec, expanded: {}, ply: {}, bucket: {} / {}",
+            rate,
+            expanded,
+            explorer.ply(),
+            explorer.bucket(),
+            BUCKETS
+        );
+        if checkpoint.is_some() {
+            explorer
+                .save_checkpoint()
+                .expect("Failed to save checkpoint");
+        }
     }
+
+    print!("{}", explorer.report());
 }
@@ -0,0 +1,44 @@
+use std::time::Instant;
+
+use ultimate_ttt::{
+    bitboard::BitGameState,
+    fmt_move,
+    perft::{perft, perft_divide},
+    GameState, RuleSet,
+};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let arg = |flag: &str| args.iter().skip_while(|arg| *arg != flag).nth(1);
+    let depth: u32 = args
+        .first()
+        .filter(|arg| !arg.starts_with("--"))
+        .map_or(6, |depth| depth.parse().expect("depth must be a number"));
+    let rules: RuleSet = arg("--rules").map_or(RuleSet::STANDARD, |rules| {
+        rules.parse().expect("unknown rules")
+    });
+    let players = arg("--players").map_or("XO", String::as_str);
+    // Also show the count below each move from the start, at the final depth
+    let divide = args.iter().any(|arg| arg == "--divide");
+
+    let state = BitGameState::from(&GameState::new(players.as_bytes(), rules));
+    for depth in 0..=depth {
+        let start = Instant::now();
+        let count = perft(&state, depth);
+        let elapsed = start.elapsed().as_secs_f64();
+        println!(
+            "depth {:2}: {:14} nodes, {:.3}s, {:.0} nodes/sec",
+            depth,
+            count,
+            elapsed,
+            count as f64 / elapsed.max(1e-9)
+        );
+    }
+
+    if divide {
+        println!();
+        for (mov, count) in perft_divide(&state, depth) {
+            println!("{:6} {}", fmt_move(mov), count);
+        }
+    }
+}
@@ -1,13 +1,60 @@
+use std::time::Duration;
+
 use ultimate_ttt::{
-    ai::{random_move, two_player_game},
+    ai::{
+        from_fn,
+        minimax::{Minimax, SearchLimits},
+        named, play_game, Agent, RandomAgent,
+    },
     human::human_player,
+    record::{write_record, Record},
+    GameState, RuleSet,
 };
 
+/// Report a bad command line and exit
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
+
 fn main() {
-    two_player_game(
-        //|state| debug_player(state, random_move),
-        random_move,
-        //|state| debug_player(state, random_move),
-        human_player,
-    );
+    let arg = |flag: &str| std::env::args().skip_while(|arg| arg != flag).nth(1);
+    let save_path = arg("--save");
+    // Play against minimax, thinking for this many milliseconds per move, instead of at random
+    let think_ms = arg("--think").map(|ms| {
+        ms.parse()
+            .unwrap_or_else(|_| usage_error("--think takes milliseconds"))
+    });
+    // Replay the random player's moves from an earlier game
+    let seed = arg("--seed").map(|seed| {
+        seed.parse()
+            .unwrap_or_else(|_| usage_error("--seed takes a number"))
+    });
+    // Minimax stops on a timer, so its moves can't be replayed from a seed
+    if seed.is_some() && think_ms.is_some() {
+        usage_error("--seed only applies to the random player, so it can't be used with --think");
+    }
+
+    let computer: Box<dyn Agent> = match think_ms {
+        Some(ms) => Box::new(Minimax::with_limits(SearchLimits::time(
+            Duration::from_millis(ms),
+        ))),
+        None => {
+            let random = seed.map_or_else(RandomAgent::from_entropy, RandomAgent::new);
+            println!("Seed: {}", random.seed());
+            Box::new(random)
+        }
+    };
+    let players: Vec<Box<dyn Agent>> =
+        vec![computer, Box::new(named("Human", from_fn(human_player)))];
+    let names = players.iter().map(|p| p.name().to_string()).collect();
+    let game = play_game(GameState::new(b"XO", RuleSet::default()), players);
+
+    if let Some(path) = save_path {
+        let mut record = Record::new(game);
+        record.names = names;
+        let text = write_record(&record).expect("Failed to write game");
+        std::fs::write(&path, text).expect("Failed to save game");
+        println!("Saved game to {}", path);
+    }
 }
@@ -1,7 +1,7 @@
 use std::collections::HashSet;
 
 use ultimate_ttt::single_board_solve::{
-    game_tree, invariant_states, print_single, SingleBoardState,
+    canonical, game_tree, print_single, scores, SingleBoardState,
 };
 
 fn main() {
@@ -13,17 +13,11 @@ fn main() {
     let mut counter = 0;
     while let Some(state) = queue.pop() {
         counter += 1;
-        if visited.contains(&state) {
+        if !visited.insert(canonical(state)) {
             continue;
         }
 
-        let invariants = invariant_states(state);
-        let (x, o) = *invariants
-            .into_iter()
-            .filter_map(|s| tree.get(&s))
-            .next()
-            .unwrap();
-        visited.extend(&invariants);
+        let (x, o) = scores(&tree, state).unwrap();
         queue.extend(state.successors());
 
         if x == 0 && o == 0 {
@@ -1,9 +1,26 @@
-use ultimate_ttt::{ai::two_player_game, human::human_player, is_superboard_won, print_game_state};
+use ultimate_ttt::{
+    ai::{from_fn, two_player_game},
+    human::human_player,
+    print_game_state,
+    record::{write_record, Record},
+    GameOutcome,
+};
 
 fn main() {
-    let state = two_player_game(human_player, human_player);
-    print_game_state(&state, None);
-    if let Some(winner) = is_superboard_won(&state.superboard) {
-        println!("{} wins!", winner);
+    let save_path = std::env::args().skip_while(|arg| arg != "--save").nth(1);
+
+    let game = two_player_game(from_fn(human_player), from_fn(human_player));
+    let state = game.state();
+    print_game_state(state, None);
+    match state.outcome() {
+        GameOutcome::Won(winner) => println!("{} wins!", winner as char),
+        GameOutcome::Draw => println!("Draw!"),
+        GameOutcome::Ongoing => (),
+    }
+
+    if let Some(path) = save_path {
+        let text = write_record(&Record::new(game)).expect("Failed to write game");
+        std::fs::write(&path, text).expect("Failed to save game");
+        println!("Saved game to {}", path);
     }
 }
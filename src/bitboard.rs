@@ -0,0 +1,613 @@
+use crate::{
+    symmetry::{map_index, MASK_SYMMETRIES},
+    zobrist, BoardResult, GameOutcome, GameState, Move, MoveError, MoveList, Player, RuleSet,
+    SuperBoard, EMPTY_SUPERBOARD, LINES, MAX_PLAYERS,
+};
+
+/// Nine bits, one per square of a board (or per sub-board of the superboard), row-major
+pub type BoardMask = u16;
+
+/// Bits set for every square of a board
+pub const FULL_MASK: BoardMask = 0x1FF;
+
+/// `WIN_TABLE[mask]` is true if `mask` contains a complete line
+pub static WIN_TABLE: [bool; 512] = build_win_table();
+
+/// `OPEN_LINE_TABLE[mask]` is true if some line has none of its squares in `mask`
+pub static OPEN_LINE_TABLE: [bool; 512] = build_open_line_table();
+
+const fn line_mask(line: [usize; 3]) -> BoardMask {
+    (1 << line[0]) | (1 << line[1]) | (1 << line[2])
+}
+
+const fn build_win_table() -> [bool; 512] {
+    let mut table = [false; 512];
+    let mut mask = 0;
+    while mask < 512 {
+        let mut i = 0;
+        while i < LINES.len() {
+            let line = line_mask(LINES[i]);
+            if mask & line == line {
+                table[mask as usize] = true;
+            }
+            i += 1;
+        }
+        mask += 1;
+    }
+    table
+}
+
+const fn build_open_line_table() -> [bool; 512] {
+    let mut table = [false; 512];
+    let mut mask = 0;
+    while mask < 512 {
+        let mut i = 0;
+        while i < LINES.len() {
+            if mask & line_mask(LINES[i]) == 0 {
+                table[mask as usize] = true;
+            }
+            i += 1;
+        }
+        mask += 1;
+    }
+    table
+}
+
+/// Returns true if this mask contains a complete line
+pub fn mask_is_won(mask: BoardMask) -> bool {
+    WIN_TABLE[mask as usize]
+}
+
+/// Iterate over the squares set in a mask, in ascending order
+pub fn squares(mask: BoardMask) -> impl Iterator<Item = usize> {
+    squares_128(mask as u128)
+}
+
+/// Iterate over the bits set in a mask of the whole superboard, in ascending order
+fn squares_128(mut mask: u128) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        (mask != 0).then(|| {
+            let square = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            square
+        })
+    })
+}
+
+/// A game state stored as one bit per square per player, with move generation and win checks
+/// done by table lookups. This is a separate engine from `GameState`, playing by the same rules,
+/// and is what perft, the tree explorer and the search agents play on. It is also smaller, for
+/// storing many states. Convert with `BitGameState::from(&state)` and `to_game_state`.
+/// As with `GameState`, the fields only change through `apply_move`, which keeps the key current.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitGameState {
+    /// Squares owned by each player (same order as `players`). Bit `9 * board + square`.
+    cells: [u128; MAX_PLAYERS],
+    /// Sub-boards won by each player (same order as `players`)
+    won: [BoardMask; MAX_PLAYERS],
+    /// Sub-boards that are either won or full
+    decided: BoardMask,
+    /// The players in this game, ordered by who goes first.
+    players: [Player; MAX_PLAYERS],
+    /// Index of the player from `players` who will make the next move
+    next_to_play: usize,
+    /// If any, the index of the superboard square the player has been sent to.
+    sent_to: Option<usize>,
+    /// Number of players
+    num_players: usize,
+    /// The rules this game is played under
+    rules: RuleSet,
+    /// Zobrist key of this state, the same as the equivalent `GameState`'s
+    key: u64,
+    /// Whether the game is over, worked out once when the state is made
+    outcome: GameOutcome,
+}
+
+impl std::hash::Hash for BitGameState {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_u64(self.key);
+    }
+}
+
+impl BitGameState {
+    /// Create a new game given player symbols and rules
+    pub fn new(players: &[Player], rules: RuleSet) -> Self {
+        Self::from_parts(&EMPTY_SUPERBOARD, &[None; 9], players, 0, None, rules)
+    }
+
+    /// Build a state from the squares and sub-board winners, without checking them against each other
+    pub(crate) fn from_parts(
+        superboard: &SuperBoard,
+        board_winners: &[Option<Player>; 9],
+        players: &[Player],
+        next_to_play: usize,
+        sent_to: Option<usize>,
+        rules: RuleSet,
+    ) -> Self {
+        assert!(
+            players.len() <= MAX_PLAYERS,
+            "MAX_PLAYERS ({}) exceeded.",
+            MAX_PLAYERS
+        );
+        let mut players_array = [b'*'; MAX_PLAYERS];
+        players_array[..players.len()].copy_from_slice(players);
+
+        let mut cells = [0; MAX_PLAYERS];
+        let mut won = [0; MAX_PLAYERS];
+        let mut decided = 0;
+        let index_of = |symbol: Player| players.iter().position(|&p| p == symbol);
+
+        for (board_idx, board) in superboard.iter().enumerate() {
+            for (square, cell) in board.iter().enumerate() {
+                if let Some(p) = cell.and_then(index_of) {
+                    cells[p] |= 1 << (9 * board_idx + square);
+                }
+            }
+
+            if let Some(p) = board_winners[board_idx].and_then(index_of) {
+                won[p] |= 1 << board_idx;
+                decided |= 1 << board_idx;
+            } else if board.iter().all(Option::is_some) {
+                decided |= 1 << board_idx;
+            }
+        }
+
+        let mut state = Self {
+            cells,
+            won,
+            decided,
+            players: players_array,
+            next_to_play,
+            sent_to,
+            num_players: players.len(),
+            rules,
+            key: 0,
+            outcome: GameOutcome::Ongoing,
+        };
+        state.key = state.compute_key();
+        state.outcome = state.compute_outcome();
+        state
+    }
+
+    /// Returns the symbol of the next player to play
+    pub fn next_to_play(&self) -> Player {
+        self.players[self.next_to_play]
+    }
+
+    /// Returns the index (into `players()`) of the next player to play
+    pub fn next_to_play_idx(&self) -> usize {
+        self.next_to_play
+    }
+
+    /// Returns the symbols of the players in this game, ordered by who goes first
+    pub fn players(&self) -> &[Player] {
+        &self.players[..self.num_players]
+    }
+
+    /// Returns the index of the sub-board the next player has been sent to, if any
+    pub fn sent_to(&self) -> Option<usize> {
+        self.sent_to
+    }
+
+    /// Returns the rules this game is played under
+    pub fn rules(&self) -> RuleSet {
+        self.rules
+    }
+
+    /// Returns the Zobrist key of this state
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    /// Work out this state's key from scratch
+    pub fn compute_key(&self) -> u64 {
+        let mut key = zobrist::to_move_key(self.next_to_play) ^ zobrist::sent_to_key(self.sent_to);
+        for p in 0..self.num_players {
+            for cell in squares_128(self.cells[p]) {
+                key ^= zobrist::cell_key(p, cell / 9, cell % 9);
+            }
+            for board in squares(self.won[p]) {
+                key ^= zobrist::winner_key(p, board);
+            }
+        }
+        key
+    }
+
+    /// Returns the winner of the given sub-board, if any. See `GameState::board_winner`.
+    pub fn board_winner(&self, board: usize) -> Option<Player> {
+        (0..self.num_players)
+            .find(|&p| self.won[p] & (1 << board) != 0)
+            .map(|p| self.players[p])
+    }
+
+    /// Returns how the given sub-board counts towards lines on the superboard
+    pub fn board_result(&self, board: usize) -> BoardResult {
+        match self.board_winner(board) {
+            Some(winner) => BoardResult::Won(winner),
+            None if self.decided & (1 << board) != 0 => BoardResult::Tied,
+            None => BoardResult::Open,
+        }
+    }
+
+    /// Returns true if no move has been made yet
+    pub fn is_first_move(&self) -> bool {
+        self.cells.iter().all(|&c| c == 0)
+    }
+
+    /// Squares of the given sub-board owned by the player at `player_idx`
+    pub fn board_mask(&self, player_idx: usize, board: usize) -> BoardMask {
+        (self.cells[player_idx] >> (9 * board)) as BoardMask & FULL_MASK
+    }
+
+    /// Squares of the given sub-board owned by anyone
+    pub fn occupied_mask(&self, board: usize) -> BoardMask {
+        (0..self.num_players).fold(0, |acc, p| acc | self.board_mask(p, board))
+    }
+
+    /// Sub-boards won by anyone
+    pub fn won_mask(&self) -> BoardMask {
+        self.won[..self.num_players]
+            .iter()
+            .fold(0, |acc, w| acc | w)
+    }
+
+    /// Squares which may still be played on the given sub-board
+    pub fn open_mask(&self, board: usize) -> BoardMask {
+        if self.won_mask() & (1 << board) != 0 && !self.rules.play_into_won_boards {
+            0
+        } else {
+            !self.occupied_mask(board) & FULL_MASK
+        }
+    }
+
+    /// Returns the sub-boards the next player may play on
+    fn playable_boards(&self) -> std::ops::Range<usize> {
+        match self.sent_to {
+            Some(send) => send..send + 1,
+            None if self.rules.centre_first_move && self.is_first_move() => 4..5,
+            None => 0..9,
+        }
+    }
+
+    /// Returns true if the player at `player_idx` has completed a line on the superboard
+    fn has_line(&self, player_idx: usize) -> bool {
+        let won = self.won[player_idx];
+        if !self.rules.ties_count_for_all {
+            return mask_is_won(won);
+        }
+
+        let tied = self.decided & !self.won_mask();
+        LINES
+            .iter()
+            .any(|&line| line_mask(line) & !(won | tied) == 0)
+    }
+
+    /// Returns whether the game has been won, drawn, or is still in progress. See `GameState::outcome`.
+    pub fn outcome(&self) -> GameOutcome {
+        self.outcome
+    }
+
+    /// Work out the outcome from the masks, for `outcome` to return from then on
+    fn compute_outcome(&self) -> GameOutcome {
+        if let Some(winner) = (0..self.num_players).find(|&p| self.has_line(p)) {
+            return GameOutcome::Won(self.players[winner]);
+        }
+
+        let undecided = !self.decided & FULL_MASK;
+        let tied = self.decided & !self.won_mask();
+        let can_win = |p: usize| {
+            // Boards which could end up counting for this player
+            let mut winnable = self.won[p];
+            for board in squares(undecided) {
+                let others = self.occupied_mask(board) & !self.board_mask(p, board);
+                if OPEN_LINE_TABLE[others as usize] {
+                    winnable |= 1 << board;
+                }
+            }
+
+            let allowed = if self.rules.ties_count_for_all {
+                winnable | tied | undecided
+            } else {
+                winnable
+            };
+
+            LINES.iter().any(|&line| line_mask(line) & !allowed == 0)
+        };
+
+        if (0..self.num_players).any(can_win) {
+            GameOutcome::Ongoing
+        } else {
+            GameOutcome::Draw
+        }
+    }
+
+    /// Return the legal moves from this state. Will return an empty vector if the game is finished.
+    pub fn successors(&self) -> Vec<Move> {
+        self.legal_moves().to_vec()
+    }
+
+    /// Return the legal moves from this state, without allocating. Will be empty if the game is finished.
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        if self.outcome() != GameOutcome::Ongoing {
+            return moves;
+        }
+
+        for superboard_idx in self.playable_boards() {
+            let superboard = self.sent_to.is_none().then_some(superboard_idx);
+            moves.extend(squares(self.open_mask(superboard_idx)).map(|square| Move {
+                superboard,
+                board: square,
+            }));
+        }
+
+        moves
+    }
+
+    /// Returns the number of legal moves from this state, without building the list
+    pub fn count_legal_moves(&self) -> usize {
+        if self.outcome() != GameOutcome::Ongoing {
+            return 0;
+        }
+
+        self.playable_boards()
+            .map(|board| self.open_mask(board).count_ones() as usize)
+            .sum()
+    }
+
+    /// Returns the `n`th move of `legal_moves()`, without building the list
+    pub fn nth_legal_move(&self, mut n: usize) -> Option<Move> {
+        if self.outcome() != GameOutcome::Ongoing {
+            return None;
+        }
+
+        for superboard_idx in self.playable_boards() {
+            let open = self.open_mask(superboard_idx);
+            let count = open.count_ones() as usize;
+            if n < count {
+                return squares(open).nth(n).map(|square| Move {
+                    superboard: self.sent_to.is_none().then_some(superboard_idx),
+                    board: square,
+                });
+            }
+            n -= count;
+        }
+
+        None
+    }
+
+    /// Apply a move to this state. Panics if the move is invalid; see `try_apply_move`
+    pub fn apply_move(&self, mov: Move) -> Self {
+        self.try_apply_move(mov)
+            .unwrap_or_else(|e| panic!("Illegal move {}: {}", crate::fmt_move(mov), e))
+    }
+
+    /// Apply a move to this state, returning an error instead if the move is invalid
+    pub fn try_apply_move(&self, mov: Move) -> Result<Self, MoveError> {
+        let players_ok = (1..=MAX_PLAYERS).contains(&self.num_players);
+        if !players_ok
+            || self.next_to_play >= self.num_players
+            || self.sent_to.is_some_and(|b| b >= 9)
+        {
+            return Err(MoveError::InvalidState);
+        }
+
+        if self.outcome() != GameOutcome::Ongoing {
+            return Err(MoveError::GameOver);
+        }
+
+        let board_idx = match (self.sent_to, mov.superboard) {
+            (Some(i), None) | (None, Some(i)) => i,
+            (Some(_), Some(_)) => return Err(MoveError::UnexpectedSuperboard),
+            (None, None) => return Err(MoveError::MissingSuperboard),
+        };
+
+        for idx in [board_idx, mov.board] {
+            if idx >= 9 {
+                return Err(MoveError::OutOfRange(idx));
+            }
+        }
+
+        if !self.playable_boards().contains(&board_idx) {
+            return Err(MoveError::NotPlayable { board: board_idx });
+        }
+
+        let already_won = self.won_mask() & (1 << board_idx) != 0;
+        if already_won && !self.rules.play_into_won_boards {
+            return Err(MoveError::BoardWon { board: board_idx });
+        }
+        if self.occupied_mask(board_idx) & (1 << mov.board) != 0 {
+            return Err(MoveError::Occupied {
+                board: board_idx,
+                square: mov.board,
+            });
+        }
+
+        // Make the move
+        let mut next = *self;
+        let player = self.next_to_play;
+        next.cells[player] |= 1 << (9 * board_idx + mov.board);
+        next.key ^= zobrist::cell_key(player, board_idx, mov.board);
+
+        if !already_won && mask_is_won(next.board_mask(player, board_idx)) {
+            next.won[player] |= 1 << board_idx;
+            next.decided |= 1 << board_idx;
+            next.key ^= zobrist::winner_key(player, board_idx);
+        } else if next.occupied_mask(board_idx) == FULL_MASK {
+            next.decided |= 1 << board_idx;
+        }
+
+        // Determine if the next player is sent
+        next.sent_to = (next.open_mask(mov.board) != 0).then_some(mov.board);
+        next.next_to_play = (self.next_to_play + 1) % self.num_players;
+
+        next.key ^= zobrist::sent_to_key(self.sent_to) ^ zobrist::sent_to_key(next.sent_to);
+        next.key ^=
+            zobrist::to_move_key(self.next_to_play) ^ zobrist::to_move_key(next.next_to_play);
+        next.outcome = next.compute_outcome();
+
+        Ok(next)
+    }
+
+    /// Apply a symmetry to the masks and `sent_to`, leaving the key for the caller to set
+    fn transform_unkeyed(&self, sym: usize) -> Self {
+        let table = &MASK_SYMMETRIES[sym];
+        let mut next = *self;
+        for p in 0..self.num_players {
+            next.cells[p] = 0;
+            for board in 0..9 {
+                let mask = table[self.board_mask(p, board) as usize];
+                next.cells[p] |= (mask as u128) << (9 * map_index(sym, board));
+            }
+            next.won[p] = table[self.won[p] as usize];
+        }
+        next.decided = table[self.decided as usize];
+        next.sent_to = self.sent_to.map(|board| map_index(sym, board));
+        next
+    }
+
+    /// Apply the symmetry with index `sym` (see `symmetry::SYMMETRIES`) to the whole state
+    pub fn transform(&self, sym: usize) -> Self {
+        let mut next = self.transform_unkeyed(sym);
+        next.key = next.compute_key();
+        next
+    }
+
+    /// The representative of this state's symmetries, along with the symmetry which takes this
+    /// state there. See `GameState::canonical_symmetry`. The symmetries are compared on their
+    /// masks, and only the one picked has its key worked out.
+    pub fn canonical_symmetry(&self) -> (Self, usize) {
+        let (mut canonical, sym) = (0..8)
+            .map(|sym| (self.transform_unkeyed(sym), sym))
+            .min_by_key(|(state, _)| (state.cells, state.won, state.sent_to))
+            .unwrap();
+        canonical.key = canonical.compute_key();
+        (canonical, sym)
+    }
+
+    /// The representative of this state's symmetries, as with `GameState::canonical_state`
+    pub fn canonical_state(&self) -> Self {
+        self.canonical_symmetry().0
+    }
+
+    /// Convert back into the array representation
+    pub fn to_game_state(&self) -> GameState {
+        GameState::from(*self)
+    }
+}
+
+impl From<BitGameState> for GameState {
+    fn from(bits: BitGameState) -> Self {
+        let superboard = std::array::from_fn(|board_idx| {
+            std::array::from_fn(|square| {
+                (0..bits.num_players)
+                    .find(|&p| bits.board_mask(p, board_idx) & (1 << square) != 0)
+                    .map(|p| bits.players[p])
+            })
+        });
+        GameState::from_parts(
+            superboard,
+            std::array::from_fn(|board| bits.board_winner(board)),
+            bits.players(),
+            bits.next_to_play,
+            bits.sent_to,
+            bits.rules,
+        )
+    }
+}
+
+impl From<&GameState> for BitGameState {
+    fn from(state: &GameState) -> Self {
+        BitGameState::from_parts(
+            state.superboard(),
+            &std::array::from_fn(|board| state.board_winner(board)),
+            state.players(),
+            state.next_to_play_idx(),
+            state.sent_to(),
+            state.rules(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::successors;
+    use rand::{prelude::SliceRandom, rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_win_table() {
+        for mask in 0..512u16 {
+            let board = std::array::from_fn(|i| (mask & (1 << i) != 0).then_some(b'X'));
+            assert_eq!(mask_is_won(mask), crate::is_board_won(&board).is_some());
+        }
+    }
+
+    /// Every combination of the variant rules
+    fn all_rules() -> impl Iterator<Item = RuleSet> {
+        (0..8).map(|bits| RuleSet {
+            play_into_won_boards: bits & 1 != 0,
+            ties_count_for_all: bits & 2 != 0,
+            centre_first_move: bits & 4 != 0,
+        })
+    }
+
+    /// Play random games on both engines side by side, checking that they agree at every step
+    #[test]
+    fn test_matches_game_state() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for rules in all_rules() {
+            for players in [&b"XO"[..], b"XOA", b"XOAB"] {
+                for _ in 0..30 {
+                    let mut state = GameState::new(players, rules);
+                    let mut bits = BitGameState::new(players, rules);
+                    loop {
+                        // Played to and built from the squares, the masks are the same
+                        assert_eq!(bits, BitGameState::from(&state));
+                        assert_eq!(bits.to_game_state(), state);
+                        assert_eq!(bits.key(), state.key());
+                        assert_eq!(bits.key(), bits.compute_key());
+
+                        assert_eq!(bits.outcome(), state.outcome());
+                        assert_eq!(bits.sent_to(), state.sent_to());
+                        assert_eq!(bits.next_to_play(), state.next_to_play());
+                        for board in 0..9 {
+                            assert_eq!(bits.board_winner(board), state.board_winner(board));
+                            assert_eq!(bits.board_result(board), state.board_result(board));
+                            assert!(squares(bits.open_mask(board)).eq(state.open_squares(board)));
+                        }
+
+                        let moves = successors(&state);
+                        assert_eq!(bits.successors(), moves);
+                        assert_eq!(bits.count_legal_moves(), state.count_legal_moves());
+                        for n in 0..=moves.len() {
+                            assert_eq!(bits.nth_legal_move(n), moves.get(n).copied());
+                        }
+
+                        // Illegal moves are turned down for the same reason
+                        for _ in 0..10 {
+                            let mov = Move {
+                                superboard: [None, Some(rng.gen_range(0..10))][rng.gen_range(0..2)],
+                                board: rng.gen_range(0..10),
+                            };
+                            assert_eq!(
+                                bits.try_apply_move(mov).map(|bits| bits.to_game_state()),
+                                state.try_apply_move(mov)
+                            );
+                        }
+
+                        for sym in 0..8 {
+                            assert_eq!(bits.transform(sym).to_game_state(), state.transform(sym));
+                        }
+
+                        let Some(&mov) = moves.choose(&mut rng) else {
+                            break;
+                        };
+                        state = state.apply_move(mov);
+                        bits = bits.apply_move(mov);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Facts about every possible sub-board, worked out once and looked up by the board's encoding.
+//!
+//! A board with at most two symbols on it is encoded in base 3, one digit per square with square
+//! 0 least significant: 0 for empty, 1 for the first symbol found and 2 for the second. Boards in
+//! games with more players may hold more symbols; those are either scanned instead, or encoded
+//! from one player's point of view with every other symbol as 2 (see `encode_for`).
+//!
+//! The winners, open squares and threats of every board are a `static` table computed at compile
+//! time, so `board_info` costs nothing extra on first use. The value of each board with best play
+//! needs the single-board solver, so `board_value` keeps those in a separate table built the first
+//! time it is called.
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::{
+    bitboard::BoardMask,
+    single_board_solve::{solve_from, SingleBoardState, Solution},
+    Board, Player, LINES,
+};
+
+/// Number of possible boards, 3^9
+pub const BOARD_COUNT: usize = 19683;
+
+/// What is known about one board. Players are numbered by their digit in the encoding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BoardInfo {
+    /// The digit (1 or 2) of the player with a line, as found by `is_board_won`
+    pub winner: Option<u8>,
+    /// Whether every square is taken
+    pub full: bool,
+    /// Squares which may still be played: empty squares on a board nobody has won
+    pub open: BoardMask,
+    /// Empty squares which would complete a line, for players 1 and 2
+    pub winning_squares: [BoardMask; 2],
+}
+
+/// Encode a board, returning the code and the symbols given digits 1 and 2.
+/// Returns None if the board holds more than two symbols.
+pub fn encode(board: &Board) -> Option<(usize, [Option<Player>; 2])> {
+    let mut symbols = [None; 2];
+    let mut code = 0;
+    let mut place = 1;
+    for square in board {
+        let digit = match *square {
+            None => 0,
+            Some(p) if symbols[0].map_or(true, |s| s == p) => {
+                symbols[0] = Some(p);
+                1
+            }
+            Some(p) if symbols[1].map_or(true, |s| s == p) => {
+                symbols[1] = Some(p);
+                2
+            }
+            Some(_) => return None,
+        };
+        code += digit * place;
+        place *= 3;
+    }
+    Some((code, symbols))
+}
+
+/// Encode a board from one player's point of view: their squares are 1 and everyone else's 2.
+/// With more than two symbols on the board, lines made from several other players' squares
+/// will look won, so only the player's own entries can be relied on.
+pub fn encode_for(board: &Board, player: Player) -> usize {
+    board.iter().rev().fold(0, |code, square| {
+        code * 3
+            + match *square {
+                None => 0,
+                Some(p) if p == player => 1,
+                Some(_) => 2,
+            }
+    })
+}
+
+/// The board with a given code, with X for 1 and O for 2
+pub fn decode(mut code: usize) -> Board {
+    std::array::from_fn(|_| {
+        let digit = code % 3;
+        code /= 3;
+        [None, Some(b'X'), Some(b'O')][digit]
+    })
+}
+
+/// Facts about every board, by code
+static TABLE: [BoardInfo; BOARD_COUNT] = build_table();
+
+/// Look up the board with a given code
+pub fn board_info(code: usize) -> &'static BoardInfo {
+    &TABLE[code]
+}
+
+/// The result with best play for the board with a given code, when player 1 or 2 is to move,
+/// for that player. The first call solves every board, twice, which takes a noticeable fraction
+/// of a second; later calls are lookups.
+pub fn board_value(code: usize) -> &'static [Solution; 2] {
+    static VALUES: OnceLock<Vec<[Solution; 2]>> = OnceLock::new();
+    &VALUES.get_or_init(build_values)[code]
+}
+
+const fn build_table() -> [BoardInfo; BOARD_COUNT] {
+    let mut table = [BoardInfo {
+        winner: None,
+        full: false,
+        open: 0,
+        winning_squares: [0; 2],
+    }; BOARD_COUNT];
+
+    let mut code = 0;
+    while code < BOARD_COUNT {
+        // The squares held by each digit
+        let mut masks: [BoardMask; 3] = [0; 3];
+        let mut rest = code;
+        let mut square = 0;
+        while square < 9 {
+            masks[rest % 3] |= 1 << square;
+            rest /= 3;
+            square += 1;
+        }
+        let empty = masks[0];
+
+        // The first line found, in the same order as `is_board_won`, decides the winner
+        let mut winner = None;
+        let mut winning_squares = [0; 2];
+        let mut i = 0;
+        while i < LINES.len() {
+            let line = (1 << LINES[i][0]) | (1 << LINES[i][1]) | (1 << LINES[i][2]);
+            let mut digit = 1;
+            while digit <= 2 {
+                let mine = masks[digit] & line;
+                if mine == line && winner.is_none() {
+                    winner = Some(digit as u8);
+                }
+                if mine.count_ones() == 2 {
+                    winning_squares[digit - 1] |= line & empty;
+                }
+                digit += 1;
+            }
+            i += 1;
+        }
+
+        table[code] = BoardInfo {
+            winner,
+            full: empty == 0,
+            open: if winner.is_some() { 0 } else { empty },
+            winning_squares,
+        };
+        code += 1;
+    }
+    table
+}
+
+fn build_values() -> Vec<[Solution; 2]> {
+    let mut solutions = HashMap::new();
+    (0..BOARD_COUNT)
+        .map(|code| {
+            let board = decode(code);
+            [true, false].map(|x_is_next| {
+                solve_from(
+                    &mut solutions,
+                    SingleBoardState::from_board(board, x_is_next),
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        board_shorthand, scan_board_won,
+        single_board_solve::{solution, solve, Value},
+    };
+
+    #[test]
+    fn test_encoding() {
+        for code in 0..BOARD_COUNT {
+            let board = decode(code);
+            let (encoded, symbols) = encode(&board).unwrap();
+            // The first symbol found may be O, in which case the digits swap
+            if symbols[0] == Some(b'O') {
+                assert_eq!(encode_for(&board, b'O'), encoded);
+                assert_eq!(decode(encoded).map(|s| s.map(|p| b'X' + b'O' - p)), board);
+            } else {
+                assert_eq!(encoded, code);
+                assert_eq!(encode_for(&board, b'X'), code);
+            }
+        }
+
+        let board = board_shorthand(*b"AB-BA----");
+        let (code, symbols) = encode(&board).unwrap();
+        assert_eq!(symbols, [Some(b'A'), Some(b'B')]);
+        assert_eq!(decode(code), board_shorthand(*b"XO-OX----"));
+        assert_eq!(encode(&board_shorthand(*b"XOA------")), None);
+        assert_eq!(
+            decode(encode_for(&board_shorthand(*b"XOA------"), b'O')),
+            board_shorthand(*b"OXO------")
+        );
+    }
+
+    #[test]
+    fn test_board_info() {
+        let info = |board| board_info(encode(&board_shorthand(board)).unwrap().0);
+
+        let empty = info(*b"---------");
+        assert_eq!(empty.winner, None);
+        assert_eq!(empty.open, 0x1FF);
+        assert_eq!(empty.winning_squares, [0, 0]);
+
+        // Both players threaten a line, and whoever moves first wins
+        let threats = info(*b"XX-OO----");
+        assert_eq!(threats.winning_squares, [1 << 2, 1 << 5]);
+
+        let won = info(*b"XXX-OO---");
+        assert_eq!(won.winner, Some(1));
+        assert_eq!(won.open, 0);
+        assert!(!won.full);
+
+        let tied = info(*b"XOXXOOOXX");
+        assert_eq!((tied.winner, tied.full, tied.open), (None, true, 0));
+
+        // The compile-time table agrees with scanning each board
+        for code in 0..BOARD_COUNT {
+            let board = decode(code);
+            let winner = scan_board_won(&board).map(|p| if p == b'X' { 1 } else { 2 });
+            assert_eq!(board_info(code).winner, winner);
+            assert_eq!(board_info(code).full, board.iter().all(Option::is_some));
+        }
+    }
+
+    #[test]
+    fn test_board_value() {
+        let value = |board| board_value(encode(&board_shorthand(board)).unwrap().0);
+        assert_eq!(value(*b"---------").map(|s| s.value), [Value::Draw; 2]);
+        // Both players threaten a line, and whoever moves first wins
+        assert_eq!(
+            value(*b"XX-OO----").map(|s| (s.value, s.distance)),
+            [(Value::Win, 1); 2]
+        );
+        assert_eq!(
+            value(*b"XXX-OO---").map(|s| s.value),
+            [Value::Win, Value::Loss]
+        );
+
+        // Values agree with the solver wherever play from the empty board can reach
+        let solutions = solve();
+        for code in 0..BOARD_COUNT {
+            let board = decode(code);
+            for (x_is_next, value) in [true, false].into_iter().zip(*board_value(code)) {
+                let state = SingleBoardState::from_board(board, x_is_next);
+                if let Some(expected) = solution(&solutions, state) {
+                    assert_eq!(value, expected);
+                }
+            }
+        }
+    }
+}
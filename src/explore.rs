@@ -0,0 +1,711 @@
+//! Breadth-first exploration of the game tree, one ply at a time.
+//!
+//! Every move adds a piece, so a state can only be reached at one ply. States are stored as
+//! canonical states (see `GameState::canonical_state`), so every state is counted exactly once
+//! up to symmetry.
+//!
+//! To keep memory bounded however wide a ply gets, the states of each ply live on disk, split by
+//! Zobrist key into `BUCKETS` files in the explorer's directory. A symmetric or repeated state
+//! always lands in the same bucket, so buckets are expanded one at a time, each read in and
+//! deduplicated on its own. The states found for the next ply are collected in memory and
+//! appended to its buckets whenever `buffer_limit` of them have built up.
+//!
+//! `save_checkpoint` appends whatever is still in memory and writes a small `checkpoint` file
+//! next to the buckets, and `from_checkpoint` carries on from it. The states themselves are
+//! never rewritten. The checkpoint is plain text, one item per line:
+//!
+//! ```text
+//! players XO
+//! max-ply 10
+//! ply 4
+//! bucket 17
+//! expanded 1234
+//! stats 1 0 0 0 0 0 0
+//! ...
+//! ```
+//!
+//! `bucket` is the bucket of `ply` being expanded and `expanded` the number of its states already
+//! done, in key order. `stats` lines give, for each ply so far, the states, terminal states, draws
+//! and wins for each of the `MAX_PLAYERS` players in turn order. `max-ply` is `-` for no limit.
+//! Player symbols are written as the char with the same value.
+//!
+//! A run stopped between checkpoints picks up from the last one. The states it had appended since
+//! are found again and deduplicated along with the rest, so nothing is counted twice.
+
+use std::{
+    fs::OpenOptions,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bitboard::BitGameState, zobrist::ZobristSet, GameOutcome, GameState, Player, MAX_PLAYERS,
+};
+
+/// Number of files each ply's states are split between
+pub const BUCKETS: usize = 256;
+
+/// States of the next ply held in memory before they are appended to their buckets
+pub const DEFAULT_BUFFER_LIMIT: usize = 1 << 20;
+
+/// Name of the checkpoint file in the explorer's directory
+const CHECKPOINT_FILE: &str = "checkpoint";
+
+/// Tallies for the states found at one ply
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PlyStats {
+    /// Distinct states, up to symmetry
+    pub states: u64,
+    /// States where the game is over
+    pub terminal: u64,
+    /// Terminal states which are draws
+    pub draws: u64,
+    /// Terminal states won by each player, in turn order
+    pub wins: [u64; MAX_PLAYERS],
+}
+
+/// Problems found while reading a checkpoint
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckpointError {
+    /// This line could not be understood
+    BadLine { line: usize },
+    /// A required line was missing
+    Missing(&'static str),
+    /// The checkpoint or a bucket could not be read or repaired
+    Io(String),
+}
+
+impl std::fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckpointError::BadLine { line } => write!(f, "line {}: not understood", line),
+            CheckpointError::Missing(what) => write!(f, "missing \"{}\" line", what),
+            CheckpointError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+/// A breadth-first search over the game tree, which can be run a few states at a time
+#[derive(Clone, Debug)]
+pub struct Explorer {
+    /// The players, in turn order
+    pub players: Vec<Player>,
+    /// Don't expand states this many plies from the start. None explores every game to its end.
+    pub max_ply: Option<usize>,
+    /// Tallies for each ply reached so far, counting from the start state
+    pub stats: Vec<PlyStats>,
+    /// Most states of the next ply to hold in memory before appending them to their buckets
+    pub buffer_limit: usize,
+    /// Where the buckets and checkpoint are kept
+    dir: PathBuf,
+    /// The ply being expanded
+    ply: usize,
+    /// The bucket of `ply` being expanded, or `BUCKETS` once everything is done
+    bucket: usize,
+    /// The states of `bucket`, in key order, once it has been read in
+    frontier: Option<Vec<BitGameState>>,
+    /// Number of states of `frontier` already expanded
+    expanded: usize,
+    /// States found for the next ply which haven't been appended to their buckets yet
+    next: ZobristSet<BitGameState>,
+}
+
+impl Explorer {
+    /// Start exploring from the given state, keeping states in `dir`.
+    /// The directory is created if needed, and must not already hold an exploration.
+    pub fn new(
+        state: &GameState,
+        max_ply: Option<usize>,
+        dir: impl Into<PathBuf>,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        if std::fs::read_dir(&dir)?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not empty", dir.display()),
+            ));
+        }
+
+        // States are kept in the position notation, so the players must be able to appear in it
+        state
+            .to_notation()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+        let start = BitGameState::from(&state.canonical_state());
+        let explorer = Self {
+            players: state.players().to_vec(),
+            max_ply,
+            stats: vec![],
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+            dir,
+            ply: 0,
+            bucket: 0,
+            frontier: None,
+            expanded: 0,
+            next: ZobristSet::default(),
+        };
+        explorer.append(0, &[start])?;
+        Ok(explorer)
+    }
+
+    /// The ply being expanded
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    /// The bucket of the current ply being expanded, out of `BUCKETS`
+    pub fn bucket(&self) -> usize {
+        self.bucket
+    }
+
+    /// Returns true once every state has been expanded
+    pub fn is_done(&self) -> bool {
+        self.bucket == BUCKETS
+    }
+
+    /// Expand up to `count` states, returning how many were expanded
+    pub fn step(&mut self, count: usize) -> io::Result<usize> {
+        let mut done = 0;
+        while done < count && !self.is_done() {
+            let frontier = match &self.frontier {
+                Some(frontier) => frontier,
+                None => self
+                    .frontier
+                    .insert(self.read_bucket(self.ply, self.bucket)?),
+            };
+
+            match frontier.get(self.expanded) {
+                Some(&state) => {
+                    self.expanded += 1;
+                    self.expand(state)?;
+                    done += 1;
+                }
+                None => self.next_bucket()?,
+            }
+        }
+        Ok(done)
+    }
+
+    /// Expand every remaining state
+    pub fn run(&mut self) -> io::Result<()> {
+        while self.step(usize::MAX)? > 0 {}
+        Ok(())
+    }
+
+    fn expand(&mut self, state: BitGameState) -> io::Result<()> {
+        if self.stats.len() <= self.ply {
+            self.stats.resize(self.ply + 1, PlyStats::default());
+        }
+        let stats = &mut self.stats[self.ply];
+        stats.states += 1;
+
+        match state.outcome() {
+            GameOutcome::Ongoing => {
+                if self.max_ply.map_or(true, |max| self.ply < max) {
+                    for &mov in state.legal_moves().iter() {
+                        self.next.insert(state.apply_move(mov).canonical_state());
+                    }
+                }
+            }
+            GameOutcome::Won(winner) => {
+                stats.terminal += 1;
+                let idx = self.players.iter().position(|&p| p == winner).unwrap();
+                stats.wins[idx] += 1;
+            }
+            GameOutcome::Draw => {
+                stats.terminal += 1;
+                stats.draws += 1;
+            }
+        }
+
+        if self.next.len() >= self.buffer_limit {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Move on to the next bucket, and to the next ply after the last one
+    fn next_bucket(&mut self) -> io::Result<()> {
+        self.frontier = None;
+        self.expanded = 0;
+        self.bucket += 1;
+        if self.bucket < BUCKETS {
+            return Ok(());
+        }
+
+        self.flush()?;
+        let next_ply = self.ply + 1;
+        if (0..BUCKETS).any(|bucket| self.bucket_path(next_ply, bucket).exists()) {
+            self.ply = next_ply;
+            self.bucket = 0;
+        }
+        Ok(())
+    }
+
+    /// Append the next ply's states held in memory to their buckets
+    fn flush(&mut self) -> io::Result<()> {
+        let states: Vec<BitGameState> = self.next.drain().collect();
+        self.append(self.ply + 1, &states)
+    }
+
+    /// Append states to the buckets of the given ply
+    fn append(&self, ply: usize, states: &[BitGameState]) -> io::Result<()> {
+        let mut buckets = vec![String::new(); BUCKETS];
+        for state in states {
+            let text = &mut buckets[state.key() as usize % BUCKETS];
+            let notation = state
+                .to_game_state()
+                .to_notation()
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+            text.push_str(&notation);
+            text.push('\n');
+        }
+
+        for (bucket, text) in buckets.iter().enumerate() {
+            if !text.is_empty() {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.bucket_path(ply, bucket))?
+                    .write_all(text.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the distinct states of a bucket, in key order
+    fn read_bucket(&self, ply: usize, bucket: usize) -> io::Result<Vec<BitGameState>> {
+        let path = self.bucket_path(ply, bucket);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(error),
+        };
+
+        let mut states = ZobristSet::default();
+        for (idx, line) in text.lines().enumerate() {
+            let state = GameState::from_notation(line).map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}, line {}: {}", path.display(), idx + 1, error),
+                )
+            })?;
+            states.insert(BitGameState::from(&state));
+        }
+
+        // Sorted, so that `expanded` picks out the same states when a run is resumed
+        let mut states: Vec<BitGameState> = states.into_iter().collect();
+        states.sort_unstable_by_key(|state| state.key());
+        Ok(states)
+    }
+
+    fn bucket_path(&self, ply: usize, bucket: usize) -> PathBuf {
+        self.dir.join(format!("ply-{}-{}.states", ply, bucket))
+    }
+
+    /// Totals over every ply
+    pub fn totals(&self) -> PlyStats {
+        self.stats
+            .iter()
+            .fold(PlyStats::default(), |mut total, ply| {
+                total.states += ply.states;
+                total.terminal += ply.terminal;
+                total.draws += ply.draws;
+                for (total, wins) in total.wins.iter_mut().zip(ply.wins) {
+                    *total += wins;
+                }
+                total
+            })
+    }
+
+    /// A table of the tallies for each ply, with totals
+    pub fn report(&self) -> String {
+        let mut report = format!("{:>4} {:>14} {:>14}", "ply", "states", "terminal");
+        for &player in &self.players {
+            report.push_str(&format!(" {:>14}", format!("{} wins", char::from(player))));
+        }
+        report.push_str(&format!(" {:>14}\n", "draws"));
+
+        let totals = self.totals();
+        let rows = self
+            .stats
+            .iter()
+            .enumerate()
+            .map(|(ply, stats)| (ply.to_string(), stats));
+        for (label, stats) in rows.chain([("total".to_string(), &totals)]) {
+            report.push_str(&format!(
+                "{:>4} {:>14} {:>14}",
+                label, stats.states, stats.terminal
+            ));
+            for wins in &stats.wins[..self.players.len()] {
+                report.push_str(&format!(" {:>14}", wins));
+            }
+            report.push_str(&format!(" {:>14}\n", stats.draws));
+        }
+        report
+    }
+
+    /// Append any states held in memory and record how far the search has got, so that
+    /// `from_checkpoint` can carry on from here. Buckets which are no longer needed are removed.
+    pub fn save_checkpoint(&mut self) -> io::Result<()> {
+        self.flush()?;
+
+        // Write to a temporary file first, so stopping mid-write can't spoil the checkpoint
+        let path = self.dir.join(CHECKPOINT_FILE);
+        let temp = self.dir.join(format!("{}.tmp", CHECKPOINT_FILE));
+        std::fs::write(&temp, self.to_checkpoint())?;
+        std::fs::rename(&temp, &path)?;
+
+        let finished = (0..self.ply)
+            .flat_map(|ply| (0..BUCKETS).map(move |bucket| (ply, bucket)))
+            .chain((0..self.bucket).map(|bucket| (self.ply, bucket)));
+        for (ply, bucket) in finished {
+            match std::fs::remove_file(self.bucket_path(ply, bucket)) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// The contents of the checkpoint file. States held in memory are not included;
+    /// `save_checkpoint` appends them to their buckets first.
+    pub fn to_checkpoint(&self) -> String {
+        let mut text = String::new();
+        let players: String = self.players.iter().map(|&p| char::from(p)).collect();
+        text.push_str(&format!("players {}\n", players));
+        match self.max_ply {
+            Some(max) => text.push_str(&format!("max-ply {}\n", max)),
+            None => text.push_str("max-ply -\n"),
+        }
+        text.push_str(&format!("ply {}\n", self.ply));
+        text.push_str(&format!("bucket {}\n", self.bucket));
+        text.push_str(&format!("expanded {}\n", self.expanded));
+        for stats in &self.stats {
+            let mut counts = vec![stats.states, stats.terminal, stats.draws];
+            counts.extend(stats.wins);
+            let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+            text.push_str(&format!("stats {}\n", counts.join(" ")));
+        }
+        text
+    }
+
+    /// Carry on from the checkpoint saved in `dir` by `save_checkpoint`
+    pub fn from_checkpoint(dir: impl Into<PathBuf>) -> Result<Self, CheckpointError> {
+        let dir = dir.into();
+        let io_error = |error: io::Error| CheckpointError::Io(error.to_string());
+        let text = std::fs::read_to_string(dir.join(CHECKPOINT_FILE)).map_err(io_error)?;
+        let explorer = Self::parse_checkpoint(&text, dir)?;
+
+        // A run stopped mid-append may have left half a line at the end of a bucket, in any of
+        // the plies it got to since the checkpoint
+        for ply in explorer.ply + 1.. {
+            let mut found = false;
+            for bucket in 0..BUCKETS {
+                found |= repair_bucket(&explorer.bucket_path(ply, bucket)).map_err(io_error)?;
+            }
+            if !found {
+                break;
+            }
+        }
+        Ok(explorer)
+    }
+
+    fn parse_checkpoint(text: &str, dir: PathBuf) -> Result<Self, CheckpointError> {
+        let mut players = None;
+        let mut max_ply = None;
+        let mut ply = None;
+        let mut bucket = None;
+        let mut expanded = None;
+        let mut stats = vec![];
+
+        for (idx, line) in text.lines().enumerate() {
+            let line_no = idx + 1;
+            let bad = || CheckpointError::BadLine { line: line_no };
+            let (tag, rest) = line.split_once(' ').ok_or_else(bad)?;
+            match tag {
+                "players" => {
+                    let symbols: Option<Vec<Player>> =
+                        rest.chars().map(|c| Player::try_from(c).ok()).collect();
+                    players = Some(symbols.ok_or_else(bad)?);
+                }
+                "max-ply" => {
+                    max_ply = Some(match rest {
+                        "-" => None,
+                        max => Some(max.parse().map_err(|_| bad())?),
+                    })
+                }
+                "ply" => ply = Some(rest.parse().map_err(|_| bad())?),
+                "bucket" => {
+                    bucket = Some(
+                        (rest.parse().ok())
+                            .filter(|&b| b <= BUCKETS)
+                            .ok_or_else(bad)?,
+                    )
+                }
+                "expanded" => expanded = Some(rest.parse().map_err(|_| bad())?),
+                "stats" => {
+                    let counts: Vec<u64> = rest
+                        .split_whitespace()
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| bad())?;
+                    let [states, terminal, draws, wins @ ..] = &counts[..] else {
+                        return Err(bad());
+                    };
+                    stats.push(PlyStats {
+                        states: *states,
+                        terminal: *terminal,
+                        draws: *draws,
+                        wins: wins.try_into().map_err(|_| bad())?,
+                    });
+                }
+                _ => return Err(bad()),
+            }
+        }
+
+        Ok(Self {
+            players: players.ok_or(CheckpointError::Missing("players"))?,
+            max_ply: max_ply.ok_or(CheckpointError::Missing("max-ply"))?,
+            stats,
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+            dir,
+            ply: ply.ok_or(CheckpointError::Missing("ply"))?,
+            bucket: bucket.ok_or(CheckpointError::Missing("bucket"))?,
+            frontier: None,
+            expanded: expanded.ok_or(CheckpointError::Missing("expanded"))?,
+            next: ZobristSet::default(),
+        })
+    }
+}
+
+/// Cut a bucket back to its last complete line, if it has one that isn't.
+/// Returns whether the bucket exists.
+fn repair_bucket(path: &Path) -> io::Result<bool> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error),
+    };
+    let len = file.seek(SeekFrom::End(0))?;
+    if len == 0 {
+        return Ok(true);
+    }
+    let mut last = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    if last[0] == b'\n' {
+        return Ok(true);
+    }
+
+    let mut text = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut text)?;
+    let keep = text.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    file.set_len(keep as u64)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board_shorthand, RuleSet, EMPTY_BOARD};
+    use std::collections::HashSet;
+
+    /// Tallies found by walking every line of play, for checking the explorer against
+    fn walk(state: GameState, ply: usize, max_ply: usize, seen: &mut Vec<HashSet<GameState>>) {
+        if seen.len() <= ply {
+            seen.resize(ply + 1, HashSet::new());
+        }
+        if !seen[ply].insert(state.canonical_state()) || ply == max_ply {
+            return;
+        }
+        for mov in state.legal_moves().iter() {
+            walk(state.apply_move(*mov), ply + 1, max_ply, seen);
+        }
+    }
+
+    fn walk_stats(state: GameState, max_ply: usize) -> Vec<PlyStats> {
+        let mut seen = vec![];
+        walk(state, 0, max_ply, &mut seen);
+        seen.iter()
+            .map(|states| {
+                let mut stats = PlyStats {
+                    states: states.len() as u64,
+                    ..PlyStats::default()
+                };
+                for state in states {
+                    match state.outcome() {
+                        GameOutcome::Ongoing => (),
+                        GameOutcome::Draw => stats.draws += 1,
+                        GameOutcome::Won(winner) => {
+                            let idx = state.players().iter().position(|&p| p == winner).unwrap();
+                            stats.wins[idx] += 1;
+                        }
+                    }
+                    stats.terminal += u64::from(state.outcome() != GameOutcome::Ongoing);
+                }
+                stats
+            })
+            .collect()
+    }
+
+    /// An empty directory for one test's states, removed again when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("ultimate_ttt-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_opening_plies() {
+        let dir = TempDir::new("opening");
+        let state = GameState::new(b"XO", RuleSet::default());
+        let mut explorer = Explorer::new(&state, Some(3), &dir.0).unwrap();
+        explorer.run().unwrap();
+        assert!(explorer.is_done());
+        assert_eq!(explorer.stats, walk_stats(state, 3));
+        assert_eq!(explorer.stats[1].states, 15);
+        assert_eq!(explorer.totals().terminal, 0);
+        assert!(explorer.report().contains("X wins"));
+
+        assert_eq!(
+            Explorer::new(&state, Some(3), &dir.0).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+    }
+
+    /// X has won boards 0 and 1 and is about to play on board 2. O has won boards 3 and 4.
+    fn endgame() -> GameState {
+        let x_board = board_shorthand(*b"XXX-O-O--");
+        let o_board = board_shorthand(*b"OOO-X-XX-");
+        let superboard = [
+            x_board,
+            x_board,
+            board_shorthand(*b"-XOO-----"),
+            o_board,
+            o_board,
+            EMPTY_BOARD,
+            board_shorthand(*b"XOXXOOO--"),
+            board_shorthand(*b"XOXXOOOXX"),
+            board_shorthand(*b"XOXOXOOXO"),
+        ];
+        GameState::new(b"XO", RuleSet::default())
+            .with_superboard(superboard)
+            .with_sent_to(Some(2))
+    }
+
+    #[test]
+    fn test_to_the_end() {
+        let dir = TempDir::new("to-the-end");
+        let state = endgame();
+        let mut explorer = Explorer::new(&state, Some(4), &dir.0).unwrap();
+        // Small enough that every ply is appended to its buckets several times
+        explorer.buffer_limit = 10;
+        explorer.run().unwrap();
+        assert_eq!(explorer.stats, walk_stats(state, 4));
+        assert!(explorer.totals().wins[0] > 0);
+    }
+
+    #[test]
+    fn test_resume() {
+        let whole_dir = TempDir::new("resume-whole");
+        let state = endgame();
+        let mut whole = Explorer::new(&state, Some(3), &whole_dir.0).unwrap();
+        whole.run().unwrap();
+
+        let dir = TempDir::new("resume");
+        let mut explorer = Explorer::new(&state, Some(3), &dir.0).unwrap();
+        let mut checkpoints = 0;
+        while !explorer.is_done() {
+            explorer.buffer_limit = 10;
+            explorer.step(50).unwrap();
+            explorer.save_checkpoint().unwrap();
+            explorer = Explorer::from_checkpoint(&dir.0).unwrap();
+            // Only the tallies grow, never the states
+            assert_eq!(
+                explorer.to_checkpoint().lines().count(),
+                5 + explorer.stats.len()
+            );
+            checkpoints += 1;
+        }
+        assert!(checkpoints > 1);
+        assert_eq!(explorer.stats, whole.stats);
+        assert_eq!(explorer.report(), whole.report());
+        // Every bucket has been expanded, so none are left
+        assert_eq!(std::fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_interrupted() {
+        let whole_dir = TempDir::new("interrupted-whole");
+        let state = endgame();
+        let mut whole = Explorer::new(&state, Some(3), &whole_dir.0).unwrap();
+        whole.run().unwrap();
+
+        let dir = TempDir::new("interrupted");
+        let mut explorer = Explorer::new(&state, Some(3), &dir.0).unwrap();
+        explorer.step(1).unwrap();
+        explorer.save_checkpoint().unwrap();
+        // Carry on without checkpointing, then stop halfway through appending a state
+        explorer.buffer_limit = 1;
+        explorer.step(20).unwrap();
+        let bucket = explorer.bucket_path(explorer.ply() + 1, 0);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&bucket)
+            .unwrap();
+        file.write_all(b"X O X 2").unwrap();
+
+        let mut explorer = Explorer::from_checkpoint(&dir.0).unwrap();
+        explorer.run().unwrap();
+        assert_eq!(explorer.stats, whole.stats);
+    }
+
+    #[test]
+    fn test_checkpoint_lines() {
+        let dir = TempDir::new("checkpoint-lines");
+        let state = GameState::new(&[0xE9, b'O'], RuleSet::default());
+        let mut explorer = Explorer::new(&state, None, &dir.0).unwrap();
+        explorer.step(1).unwrap();
+        explorer.save_checkpoint().unwrap();
+        assert!(explorer.to_checkpoint().starts_with("players \u{e9}O\n"));
+        let resumed = Explorer::from_checkpoint(&dir.0).unwrap();
+        assert_eq!(resumed.players, [0xE9, b'O']);
+        assert_eq!(resumed.max_ply, None);
+        assert_eq!(resumed.to_checkpoint(), explorer.to_checkpoint());
+
+        let parse = |text| Explorer::parse_checkpoint(text, dir.0.clone()).map(|_| ());
+        assert_eq!(
+            parse("players XO\nply 0\n"),
+            Err(CheckpointError::Missing("max-ply"))
+        );
+        assert_eq!(
+            parse("players XO\nply x\n"),
+            Err(CheckpointError::BadLine { line: 2 })
+        );
+        assert_eq!(
+            parse("players X\u{100}\n"),
+            Err(CheckpointError::BadLine { line: 1 })
+        );
+        assert_eq!(
+            parse("bucket 257\n"),
+            Err(CheckpointError::BadLine { line: 1 })
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{GameOutcome, GameState, Move, MoveError};
+
+/// What a player decided to do on their turn
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Make a move
+    Play(Move),
+    /// Take back this player's last move, and every move made since
+    Undo,
+}
+
+/// A game from its initial state, along with every move made so far.
+/// Two games are equal if they have the same history and current state; moves which
+/// have been undone but could still be redone don't count.
+#[derive(Clone, Debug)]
+pub struct Game {
+    /// Every move played, including undone moves which can still be redone
+    moves: Vec<Move>,
+    /// `states[i]` is the state after the first `i` moves; `states[0]` is the initial state
+    states: Vec<GameState>,
+    /// Number of moves currently applied
+    ply: usize,
+}
+
+impl PartialEq for Game {
+    fn eq(&self, other: &Self) -> bool {
+        self.history() == other.history() && self.state() == other.state()
+    }
+}
+
+impl Eq for Game {}
+
+/// `Game::rewind_to` was asked for more moves than have been recorded
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NotRecorded {
+    /// The ply asked for
+    pub ply: usize,
+    /// The number of moves recorded, including undone ones
+    pub recorded: usize,
+}
+
+impl std::fmt::Display for NotRecorded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "can't rewind to ply {}, only {} moves have been recorded",
+            self.ply, self.recorded
+        )
+    }
+}
+
+impl std::error::Error for NotRecorded {}
+
+impl Game {
+    /// Start a new game from the given state
+    pub fn new(initial: GameState) -> Self {
+        Self {
+            moves: vec![],
+            states: vec![initial],
+            ply: 0,
+        }
+    }
+
+    /// The state this game started from
+    pub fn initial_state(&self) -> &GameState {
+        &self.states[0]
+    }
+
+    /// The current state of the game
+    pub fn state(&self) -> &GameState {
+        &self.states[self.ply]
+    }
+
+    /// Whether the current state is won, drawn, or still in progress
+    pub fn outcome(&self) -> GameOutcome {
+        self.state().outcome()
+    }
+
+    /// Number of moves currently applied
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    /// The moves which led from the initial state to the current state
+    pub fn history(&self) -> &[Move] {
+        &self.moves[..self.ply]
+    }
+
+    /// The move which led to the current state, if any
+    pub fn last_move(&self) -> Option<Move> {
+        self.history().last().copied()
+    }
+
+    /// Make a move from the current state. Any undone moves can no longer be redone.
+    pub fn play(&mut self, mov: Move) -> Result<(), MoveError> {
+        let next = self.state().try_apply_move(mov)?;
+        self.moves.truncate(self.ply);
+        self.states.truncate(self.ply + 1);
+        self.moves.push(mov);
+        self.states.push(next);
+        self.ply += 1;
+        Ok(())
+    }
+
+    /// Take back the last move, returning it. Returns None at the start of the game.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mov = self.last_move()?;
+        self.ply -= 1;
+        Some(mov)
+    }
+
+    /// Replay the most recently undone move, returning it. Returns None if there is nothing to redo.
+    pub fn redo(&mut self) -> Option<Move> {
+        let mov = *self.moves.get(self.ply)?;
+        self.ply += 1;
+        Some(mov)
+    }
+
+    /// Undo or redo moves until `ply` moves are applied.
+    /// Returns an error and does nothing if fewer than `ply` moves have been recorded.
+    pub fn rewind_to(&mut self, ply: usize) -> Result<(), NotRecorded> {
+        if ply > self.moves.len() {
+            return Err(NotRecorded {
+                ply,
+                recorded: self.moves.len(),
+            });
+        }
+        self.ply = ply;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RuleSet;
+
+    fn mov(superboard: Option<usize>, board: usize) -> Move {
+        Move { superboard, board }
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let initial = GameState::new(b"XO", RuleSet::default());
+        let mut game = Game::new(initial);
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.redo(), None);
+
+        game.play(mov(Some(4), 0)).unwrap();
+        game.play(mov(None, 8)).unwrap();
+        game.play(mov(None, 4)).unwrap();
+        assert_eq!(game.ply(), 3);
+        assert_eq!(game.last_move(), Some(mov(None, 4)));
+        let end = *game.state();
+
+        assert_eq!(game.undo(), Some(mov(None, 4)));
+        assert_eq!(game.undo(), Some(mov(None, 8)));
+        assert_eq!(game.history(), &[mov(Some(4), 0)]);
+        assert_eq!(*game.state(), initial.apply_move(mov(Some(4), 0)));
+
+        assert_eq!(game.redo(), Some(mov(None, 8)));
+        assert_eq!(game.redo(), Some(mov(None, 4)));
+        assert_eq!(game.redo(), None);
+        assert_eq!(*game.state(), end);
+
+        assert_eq!(game.rewind_to(0), Ok(()));
+        assert_eq!(game.state(), game.initial_state());
+        assert_eq!(game.rewind_to(3), Ok(()));
+        assert_eq!(*game.state(), end);
+        assert_eq!(
+            game.rewind_to(4),
+            Err(NotRecorded {
+                ply: 4,
+                recorded: 3
+            })
+        );
+        assert_eq!(game.ply(), 3);
+
+        // Playing after an undo discards the undone moves
+        game.rewind_to(1).unwrap();
+        game.play(mov(None, 0)).unwrap();
+        assert_eq!(game.history(), &[mov(Some(4), 0), mov(None, 0)]);
+        assert_eq!(game.redo(), None);
+    }
+
+    #[test]
+    fn test_equality_ignores_redo() {
+        let mut game = Game::new(GameState::new(b"XO", RuleSet::default()));
+        let fresh = game.clone();
+        game.play(mov(Some(4), 0)).unwrap();
+        assert_ne!(game, fresh);
+        game.undo();
+        assert_eq!(game, fresh);
+    }
+
+    #[test]
+    fn test_illegal_play() {
+        let mut game = Game::new(GameState::new(b"XO", RuleSet::default()));
+        assert_eq!(game.play(mov(None, 0)), Err(MoveError::MissingSuperboard));
+        assert_eq!(game.ply(), 0);
+    }
+}
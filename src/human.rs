@@ -1,60 +1,79 @@
 use std::fmt::Display;
 use std::io::Write;
 
-use crate::{fmt_move, print_game_state, successors, GamePrintGuides, GameState, Move};
+use crate::{fmt_move, print_game_state, successors, Action, Game, GamePrintGuides, Move};
 
-pub fn human_player(state: GameState) -> Option<Move> {
+/// Text input accepted when a human picks a square
+enum Input {
+    Coord(usize),
+    Undo,
+}
+
+/// Ask at the terminal for a move, or to undo. Use `ai::from_fn` to make this an agent.
+pub fn human_player(game: &Game) -> Option<Action> {
+    let state = *game.state();
     let succ = successors(&state);
     if succ.is_empty() {
         return None;
     }
 
+    let can_undo = game.ply() >= state.num_players();
+
     print!("Possible moves are: ");
     for &mov in &succ {
         print!("{}, ", fmt_move(mov));
     }
     println!();
+    if can_undo {
+        println!("Type \"undo\" to take back your last move.");
+    }
 
     loop {
         let mut picked_superboard_idx = None;
-        if state.sent_to.is_none() {
+        if state.sent_to().is_none() {
             print_game_state(&state, Some(GamePrintGuides::Superboard));
-            picked_superboard_idx = Some(prompt_parse("Please pick a sub-board", parse_coord));
+            match prompt_parse("Please pick a sub-board", parse_input) {
+                Input::Coord(idx) => picked_superboard_idx = Some(idx),
+                Input::Undo if can_undo => break Some(Action::Undo),
+                Input::Undo => {
+                    println!("Nothing to undo!");
+                    continue;
+                }
+            }
         }
 
-        let superboard_idx = picked_superboard_idx.or(state.sent_to).unwrap();
+        let superboard_idx = picked_superboard_idx.or(state.sent_to()).unwrap();
         print_game_state(&state, Some(GamePrintGuides::Board(superboard_idx)));
-        let board_idx = prompt_parse("Please pick a square on the sub-board", parse_coord);
+        let board_idx = match prompt_parse("Please pick a square on the sub-board", parse_input) {
+            Input::Coord(idx) => idx,
+            Input::Undo if can_undo => break Some(Action::Undo),
+            Input::Undo => {
+                println!("Nothing to undo!");
+                continue;
+            }
+        };
 
         let mov = Move {
             superboard: picked_superboard_idx,
             board: board_idx,
         };
         if succ.contains(&mov) {
-            break Some(mov);
+            break Some(Action::Play(mov));
         } else {
             println!("Invalid move!");
         }
     }
 }
 
-fn parse_coord(s: String) -> Option<usize> {
-    let mut c = s.chars();
-
-    let columns = ['a', 'b', 'c', 'A', 'B', 'C'];
-    let rows = ['1', '2', '3'];
-
-    let (row, col) = match c.next().zip(c.next())? {
-        (a, b) if rows.contains(&a) && columns.contains(&b) => (a, b),
-        (a, b) if columns.contains(&a) && rows.contains(&b) => (b, a),
-        _ => return None,
-    };
-
-    let col = col.to_ascii_lowercase() as u8 - 'a' as u8;
-    let row = row as u8 - '1' as u8;
-    let idx = (row * 3 + col) as usize;
+fn parse_input(s: String) -> Option<Input> {
+    if s.trim().eq_ignore_ascii_case("undo") {
+        return Some(Input::Undo);
+    }
+    parse_coord(s).map(Input::Coord)
+}
 
-    Some(idx)
+fn parse_coord(s: String) -> Option<usize> {
+    crate::parse_coord(&s).ok()
 }
 
 fn prompt_parse<T>(msg: impl Display + Copy, parser: fn(String) -> Option<T>) -> T {
@@ -1,6 +1,25 @@
 pub mod ai;
+pub mod bitboard;
+pub mod board_table;
+pub mod explore;
+pub mod game;
 pub mod human;
+pub mod move_list;
+pub mod nested;
+pub mod notation;
+pub mod perft;
+pub mod record;
+pub mod rules;
+#[cfg(feature = "serde")]
+pub mod serde_grid;
 pub mod single_board_solve;
+pub mod sized;
+pub mod symmetry;
+pub mod zobrist;
+
+pub use game::{Action, Game, NotRecorded};
+pub use move_list::MoveList;
+pub use rules::{BoardResult, RuleSet};
 
 /// A Player
 pub type Player = u8;
@@ -25,6 +44,7 @@ pub const MAX_PLAYERS: usize = 4;
 
 /// One move to be applied to a game state
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Move {
     /// If the user has the choice to pick the superboard square of their next move, this is Some(index) and otherwise None
     pub superboard: Option<usize>,
@@ -32,11 +52,29 @@ pub struct Move {
     pub board: usize,
 }
 
-/// A single game state
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+/// A single game state, worked out directly on the arrays of squares. `bitboard::BitGameState`
+/// is a faster engine for the same rules, which searches play on; the two are tested against
+/// each other.
+///
+/// The fields may be read and set directly. Nothing else is stored, so the Zobrist key and the
+/// outcome are always worked out from them and can't go stale.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        into = "serde_grid::GameStateRepr",
+        try_from = "serde_grid::GameStateRepr"
+    )
+)]
 pub struct GameState {
     /// The board state
     pub superboard: SuperBoard,
+    /// The first player to win each sub-board, as recorded by `apply_move`. With
+    /// `RuleSet::play_into_won_boards` a board may later gain a line for another player too, and
+    /// this says who won it first. `board_winner` checks it against the squares, so a state set up
+    /// by hand may leave it as `[None; 9]`.
+    pub board_winners: [Option<Player>; 9],
     /// The players in this game, ordered by who goes first.
     pub players: [Player; MAX_PLAYERS], // TODO: Try using a Vec<> and profiling...
     /// Index of the player from GameSetup::players who will make the next move
@@ -45,43 +83,36 @@ pub struct GameState {
     pub sent_to: Option<usize>,
     /// Number of players
     pub num_players: usize,
+    /// The rules this game is played under
+    pub rules: RuleSet,
 }
 
-/// Return the successors of the given game state. Will return an empty vector if the game is finished.
-pub fn successors(state: &GameState) -> Vec<Move> {
-    // Game is already won
-    if is_superboard_won(&state.superboard).is_some() {
-        return vec![];
+impl std::hash::Hash for GameState {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_u64(self.key());
     }
+}
 
-    if let Some(send) = state.sent_to {
-        // We've been sent to a specific square, make moves there
-        open_board_squares(state.superboard[send])
-            .map(|square| Move {
-                superboard: None,
-                board: square,
-            })
-            .collect()
-    } else {
-        // Return moves for all possible squares
-        state
-            .superboard
-            .iter()
-            .enumerate()
-            .map(|(superboard_idx, board)| {
-                open_board_squares(*board).map(move |square| Move {
-                    superboard: Some(superboard_idx),
-                    board: square,
-                })
-            })
-            .flatten()
-            .collect()
-    }
+/// Return the successors of the given game state. Will return an empty vector if the game is finished.
+pub fn successors(state: &GameState) -> Vec<Move> {
+    state.legal_moves().to_vec()
 }
 
 impl GameState {
-    /// Create a new game given player symbols
-    pub fn new(players: &[Player]) -> Self {
+    /// Create a new game given player symbols and rules
+    pub fn new(players: &[Player], rules: RuleSet) -> Self {
+        Self::from_parts(EMPTY_SUPERBOARD, [None; 9], players, 0, None, rules)
+    }
+
+    /// Build a state from its parts, without checking them against each other
+    pub(crate) fn from_parts(
+        superboard: SuperBoard,
+        board_winners: [Option<Player>; 9],
+        players: &[Player],
+        next_to_play: usize,
+        sent_to: Option<usize>,
+        rules: RuleSet,
+    ) -> Self {
         let mut players_array = [b'*'; MAX_PLAYERS];
         assert!(
             players.len() <= MAX_PLAYERS,
@@ -91,126 +122,405 @@ impl GameState {
         players_array[..players.len()].copy_from_slice(players);
 
         Self {
-            superboard: EMPTY_SUPERBOARD,
-            next_to_play: 0,
-            sent_to: None,
+            superboard,
+            board_winners,
             players: players_array,
+            next_to_play,
+            sent_to,
             num_players: players.len(),
+            rules,
+        }
+    }
+
+    /// Replace the superboard, working out the sub-board winners from scratch with `is_board_won`.
+    /// Useful for setting up positions by hand.
+    pub fn with_superboard(self, superboard: SuperBoard) -> Self {
+        Self {
+            superboard,
+            board_winners: superboard.map(|board| is_board_won(&board)),
+            ..self
         }
     }
 
+    /// Set the index (into `players()`) of the player to move. Useful for setting up positions by hand.
+    pub fn with_next_to_play(self, player_idx: usize) -> Self {
+        assert!(player_idx < self.num_players, "No player {}", player_idx);
+        Self {
+            next_to_play: player_idx,
+            ..self
+        }
+    }
+
+    /// Set the sub-board the player to move was sent to. Useful for setting up positions by hand.
+    pub fn with_sent_to(self, sent_to: Option<usize>) -> Self {
+        assert!(
+            sent_to.map_or(true, |board| board < 9),
+            "No sub-board {:?}",
+            sent_to
+        );
+        Self { sent_to, ..self }
+    }
+
     /// Returns the symbol of the next player to play
     pub fn next_to_play(&self) -> Player {
         self.players[self.next_to_play]
     }
 
-    /// Apply a move to this state. Checks whether the move is valid
+    /// Returns the index (into `players()`) of the next player to play
+    pub fn next_to_play_idx(&self) -> usize {
+        self.next_to_play
+    }
+
+    /// Returns the symbols of the players in this game, ordered by who goes first
+    pub fn players(&self) -> &[Player] {
+        &self.players[..self.num_players]
+    }
+
+    /// Returns the number of players in this game
+    pub fn num_players(&self) -> usize {
+        self.num_players
+    }
+
+    /// Returns the squares of every sub-board
+    pub fn superboard(&self) -> &SuperBoard {
+        &self.superboard
+    }
+
+    /// Returns the winner of the given sub-board, if any. Under `RuleSet::play_into_won_boards`
+    /// this is whoever won it first, even if another player has since made a line there too.
+    pub fn board_winner(&self, board_idx: usize) -> Option<Player> {
+        let board = &self.superboard[board_idx];
+        let has_line = |&player: &Player| {
+            LINES
+                .iter()
+                .any(|line| line.iter().all(|&idx| board[idx] == Some(player)))
+        };
+        self.board_winners[board_idx]
+            .filter(has_line)
+            .or_else(|| is_board_won(board))
+    }
+
+    /// Returns the index of the sub-board the next player has been sent to, if any
+    pub fn sent_to(&self) -> Option<usize> {
+        self.sent_to
+    }
+
+    /// Returns the rules this game is played under
+    pub fn rules(&self) -> RuleSet {
+        self.rules
+    }
+
+    /// Returns the Zobrist key of this state (see the `zobrist` module)
+    pub fn key(&self) -> u64 {
+        self.compute_key()
+    }
+
+    /// Returns how the given sub-board counts towards lines on the superboard
+    pub fn board_result(&self, board_idx: usize) -> BoardResult {
+        match self.board_winner(board_idx) {
+            Some(winner) => BoardResult::Won(winner),
+            None if self.superboard[board_idx].iter().all(Option::is_some) => BoardResult::Tied,
+            None => BoardResult::Open,
+        }
+    }
+
+    /// Returns the squares which may be played on the given sub-board under this game's rules
+    pub fn open_squares(&self, board_idx: usize) -> impl Iterator<Item = usize> {
+        let closed = self.board_winner(board_idx).is_some() && !self.rules.play_into_won_boards;
+        self.superboard[board_idx]
+            .into_iter()
+            .enumerate()
+            .filter(move |(_, square)| !closed && square.is_none())
+            .map(|(idx, _)| idx)
+    }
+
+    /// Returns true if no move has been made yet
+    pub(crate) fn is_first_move(&self) -> bool {
+        self.superboard == EMPTY_SUPERBOARD
+    }
+
+    /// Returns the sub-boards the next player may play on
+    fn playable_boards(&self) -> std::ops::Range<usize> {
+        match self.sent_to {
+            Some(send) => send..send + 1,
+            None if self.rules.centre_first_move && self.is_first_move() => 4..5,
+            None => 0..9,
+        }
+    }
+
+    /// Returns whether the game has been won, drawn, or is still in progress.
+    /// A game is drawn early once no player can complete a line on the superboard.
+    pub fn outcome(&self) -> GameOutcome {
+        sized::outcome(
+            3,
+            3,
+            self.players(),
+            self.rules,
+            |idx| self.board_result(idx),
+            |idx, player| board_can_be_won_by(&self.superboard[idx], player),
+        )
+    }
+
+    /// Return the legal moves from this state, without allocating. Will be empty if the game is finished.
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+
+        // Game is already won or drawn
+        if self.outcome() != GameOutcome::Ongoing {
+            return moves;
+        }
+
+        for superboard_idx in self.playable_boards() {
+            let superboard = self.sent_to.is_none().then_some(superboard_idx);
+            moves.extend(self.open_squares(superboard_idx).map(|square| Move {
+                superboard,
+                board: square,
+            }));
+        }
+
+        moves
+    }
+
+    /// Returns the number of legal moves from this state, without building the list
+    pub fn count_legal_moves(&self) -> usize {
+        if self.outcome() != GameOutcome::Ongoing {
+            return 0;
+        }
+
+        self.playable_boards()
+            .map(|board| self.open_squares(board).count())
+            .sum()
+    }
+
+    /// Returns the `n`th move of `legal_moves()`, without building the list
+    pub fn nth_legal_move(&self, mut n: usize) -> Option<Move> {
+        if self.outcome() != GameOutcome::Ongoing {
+            return None;
+        }
+
+        for superboard_idx in self.playable_boards() {
+            let count = self.open_squares(superboard_idx).count();
+            if n < count {
+                return self.open_squares(superboard_idx).nth(n).map(|square| Move {
+                    superboard: self.sent_to.is_none().then_some(superboard_idx),
+                    board: square,
+                });
+            }
+            n -= count;
+        }
+
+        None
+    }
+
+    /// Apply a move to this state. Panics if the move is invalid; see `try_apply_move`
     pub fn apply_move(&self, mov: Move) -> Self {
+        self.try_apply_move(mov)
+            .unwrap_or_else(|e| panic!("Illegal move {}: {}", fmt_move(mov), e))
+    }
+
+    /// Apply a move to this state, returning an error instead if the move is invalid
+    pub fn try_apply_move(&self, mov: Move) -> Result<Self, MoveError> {
+        // Fields set by hand (or deserialized) may not describe a game at all
+        let players_ok = (1..=MAX_PLAYERS).contains(&self.num_players);
+        if !players_ok
+            || self.next_to_play >= self.num_players
+            || self.sent_to.is_some_and(|b| b >= 9)
+        {
+            return Err(MoveError::InvalidState);
+        }
+
+        if self.outcome() != GameOutcome::Ongoing {
+            return Err(MoveError::GameOver);
+        }
+
         // Check if the superboard move is legal
-        // TODO: Disable this in release mode?
         let board_idx = match (self.sent_to, mov.superboard) {
             (Some(i), None) | (None, Some(i)) => i,
-            (Some(_), Some(_)) => panic!("Move included superboard index when not needed"),
-            (None, None) => panic!("Move did not include superboard index when needed"),
+            (Some(_), Some(_)) => return Err(MoveError::UnexpectedSuperboard),
+            (None, None) => return Err(MoveError::MissingSuperboard),
         };
 
+        for idx in [board_idx, mov.board] {
+            if idx >= 9 {
+                return Err(MoveError::OutOfRange(idx));
+            }
+        }
+
+        if !self.playable_boards().contains(&board_idx) {
+            return Err(MoveError::NotPlayable { board: board_idx });
+        }
+
         // Check if the board move is legal
         let mut board = self.superboard[board_idx];
+        let winner = self.board_winner(board_idx);
+        if winner.is_some() && !self.rules.play_into_won_boards {
+            return Err(MoveError::BoardWon { board: board_idx });
+        }
         if board[mov.board].is_some() {
-            panic!("Illegal move in sub-board {}; {}", board_idx, mov.board);
+            return Err(MoveError::Occupied {
+                board: board_idx,
+                square: mov.board,
+            });
         }
 
         // Make the move
         let player = self.players[self.next_to_play];
         board[mov.board] = Some(player);
 
-        let mut superboard = self.superboard;
-        superboard[board_idx] = board;
+        let mut next = *self;
+        next.superboard[board_idx] = board;
+
+        // Only the player who just moved can have completed a new line
+        next.board_winners[board_idx] = winner.or_else(|| is_board_won(&board));
 
         // Determine if the next player is sent
-        let sent_to = open_board_squares(superboard[mov.board])
+        next.sent_to = next
+            .open_squares(mov.board)
             .next()
             .is_some()
-            .then(|| mov.board);
+            .then_some(mov.board);
 
         // Calculate the next player
-        let next_to_play = (self.next_to_play + 1) % self.num_players;
+        next.next_to_play = (self.next_to_play + 1) % self.num_players;
 
-        Self {
-            superboard,
-            next_to_play,
-            sent_to,
-            players: self.players,
-            num_players: self.num_players,
-        }
+        Ok(next)
     }
 }
 
-/// Returns an iterator over the squares yet to be populated in this board
-pub fn open_board_squares(board: Board) -> impl Iterator<Item = usize> {
-    let is_won = is_board_won(&board).is_some();
-    board
-        .into_iter()
-        .enumerate()
-        .filter_map(|(idx, square)| (square == Square::None).then(|| idx))
-        .filter(move |_| !is_won)
+/// The result of a game, or the lack of one
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameOutcome {
+    /// The game is still in progress
+    Ongoing,
+    /// The given player has won
+    Won(Player),
+    /// No player can win anymore
+    Draw,
 }
 
-/// Returns Some(winner) if these sequares are won  
-pub fn row_is_won(row: [Square; 3]) -> Option<Player> {
-    row[0].and_then(|player| (row[1] == row[0] && row[2] == row[0]).then(|| player))
+/// Reasons a move may be rejected by `GameState::try_apply_move`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MoveError {
+    /// The game is already over
+    GameOver,
+    /// A superboard or board index was not in `0..9`
+    OutOfRange(usize),
+    /// The player has a free choice of sub-board, but the move did not include one
+    MissingSuperboard,
+    /// The player was sent to a sub-board, but the move included a superboard index anyway
+    UnexpectedSuperboard,
+    /// The square is already taken
+    Occupied { board: usize, square: usize },
+    /// The sub-board has already been won
+    BoardWon { board: usize },
+    /// The sub-board can't be played this turn, e.g. because the first move must be made in the centre
+    NotPlayable { board: usize },
+    /// The state itself is broken: it has no players or too many, or an out-of-range
+    /// player to move or sent-to board
+    InvalidState,
 }
 
-/// Returns Some(winner) if this board is won  
-pub fn is_board_won(board: &Board) -> Option<Player> {
-    for row in board.chunks_exact(3) {
-        let ret = row_is_won([row[0], row[1], row[2]]);
-        if ret.is_some() {
-            return ret;
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "the game is already over"),
+            MoveError::OutOfRange(idx) => write!(f, "index {} is out of range", idx),
+            MoveError::MissingSuperboard => {
+                write!(f, "move did not include superboard index when needed")
+            }
+            MoveError::UnexpectedSuperboard => {
+                write!(f, "move included superboard index when not needed")
+            }
+            MoveError::Occupied { board, square } => {
+                write!(f, "square {} of sub-board {} is occupied", square, board)
+            }
+            MoveError::BoardWon { board } => write!(f, "sub-board {} is already won", board),
+            MoveError::NotPlayable { board } => {
+                write!(f, "sub-board {} can't be played this turn", board)
+            }
+            MoveError::InvalidState => write!(f, "the game state is invalid"),
         }
     }
+}
 
-    for col_idx in 0..3 {
-        let row = [
-            board[3 * 0 + col_idx],
-            board[3 * 1 + col_idx],
-            board[3 * 2 + col_idx],
-        ];
-        let ret = row_is_won(row);
-        if ret.is_some() {
-            return ret;
-        }
-    }
+impl std::error::Error for MoveError {}
 
-    let row = [board[3 * 0 + 0], board[3 * 1 + 1], board[3 * 2 + 2]];
+/// Returns an iterator over the squares yet to be populated in this board
+pub fn open_board_squares(board: Board) -> impl Iterator<Item = usize> {
+    let open = match board_table::encode(&board) {
+        Some((code, _)) => board_table::board_info(code).open,
+        None if is_board_won(&board).is_some() => 0,
+        None => (0..9)
+            .filter(|&idx| board[idx].is_none())
+            .fold(0, |mask, idx| mask | 1 << idx),
+    };
+    bitboard::squares(open)
+}
 
-    let ret = row_is_won(row);
-    if ret.is_some() {
-        return ret;
-    }
+/// Indices of every winning line on a board (rows, columns and diagonals)
+pub const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+/// Returns true if the given player could still complete a line on this board
+pub fn board_can_be_won_by(board: &Board, player: Player) -> bool {
+    sized::can_complete_line(3, 3, |idx| board[idx], player)
+}
 
-    let row = [board[3 * 0 + 2], board[3 * 1 + 1], board[3 * 2 + 0]];
+/// Returns Some(winner) if these sequares are won  
+pub fn row_is_won(row: [Square; 3]) -> Option<Player> {
+    row[0].and_then(|player| (row[1] == row[0] && row[2] == row[0]).then_some(player))
+}
 
-    let ret = row_is_won(row);
-    if ret.is_some() {
-        return ret;
+/// Returns Some(winner) if this board is won  
+pub fn is_board_won(board: &Board) -> Option<Player> {
+    match board_table::encode(board) {
+        Some((code, symbols)) => board_table::board_info(code)
+            .winner
+            .and_then(|digit| symbols[digit as usize - 1]),
+        None => scan_board_won(board),
     }
+}
 
-    None
+/// Returns Some(winner) if this board is won, checking each line in turn.
+/// Used for boards `board_table` can't encode, and to check the table against.
+pub(crate) fn scan_board_won(board: &Board) -> Option<Player> {
+    sized::find_line(3, 3, |idx| board[idx])
 }
 
-/// Returns true if this superboard is won
-pub fn is_superboard_won(superboard: &SuperBoard) -> Option<Player> {
-    is_board_won(&superboard.map(|b| is_board_won(&b)))
+/// Returns Some(winner) if a player has a line on this superboard under the given rules.
+/// Sub-board winners are worked out with `is_board_won`; prefer `GameState::outcome` when a state is available.
+pub fn is_superboard_won(superboard: &SuperBoard, rules: &RuleSet) -> Option<Player> {
+    let results = superboard.map(|board| match is_board_won(&board) {
+        Some(winner) => BoardResult::Won(winner),
+        None if board.iter().all(Option::is_some) => BoardResult::Tied,
+        None => BoardResult::Open,
+    });
+    let mut players: Vec<Player> = superboard.iter().flatten().flatten().copied().collect();
+    players.sort_unstable();
+    players.dedup();
+
+    players
+        .into_iter()
+        .find(|&player| rules.has_line(&results, player))
 }
 
 /// Parse an array of characters into a board
 pub fn board_shorthand(chars: [u8; 9]) -> Board {
-    chars.map(|c| (c != b'-').then(|| c))
+    chars.map(|c| (c != b'-').then_some(c))
 }
 
 /// Printing options for game state
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GamePrintGuides {
     /// Print guides for the superboard
     Superboard,
@@ -220,10 +530,10 @@ pub enum GamePrintGuides {
 
 /// Print the given game state, optionally showing guides
 pub fn print_game_state(state: &GameState, guides: Option<GamePrintGuides>) {
-    if let Some(winner) = is_superboard_won(&state.superboard) {
-        println!("{} won.", winner as char);
-    } else {
-        println!("{} to play.", state.players[state.next_to_play] as char);
+    match state.outcome() {
+        GameOutcome::Won(winner) => println!("{} won.", winner as char),
+        GameOutcome::Draw => println!("Draw."),
+        GameOutcome::Ongoing => println!("{} to play.", state.next_to_play() as char),
     }
 
     print_superboard(&state.superboard, guides);
@@ -231,56 +541,17 @@ pub fn print_game_state(state: &GameState, guides: Option<GamePrintGuides>) {
 
 /// Print a superboard, optionally showing guides
 pub fn print_superboard(superboard: &SuperBoard, guides: Option<GamePrintGuides>) {
-    for (superboard_row_idx, superboard_row) in superboard.chunks_exact(3).enumerate() {
-        // Headers for this superboard row, if any
-        print!(" ");
-        for superboard_column_idx in 0..3 {
-            print!(" ");
-            let superboard_idx = superboard_row_idx * 3 + superboard_column_idx;
-            match guides {
-                Some(GamePrintGuides::Board(board_idx)) if board_idx == superboard_idx => {
-                    print!("A B C");
-                }
-                Some(GamePrintGuides::Superboard) if superboard_row_idx == 0 => {
-                    print!("  {}  ", ['A', 'B', 'C'][superboard_column_idx]);
-                }
-                _ => print!("     "),
-            }
-            print!(" ");
-        }
-        println!();
-
-        // Print board cells
-        for board_row in 0..3 {
-            for superboard_column_idx in 0..3 {
-                let superboard_idx = superboard_row_idx * 3 + superboard_column_idx;
-                match guides {
-                    Some(GamePrintGuides::Board(board_idx)) if superboard_idx == board_idx => {
-                        print!(" {}", board_row + 1);
-                    }
-                    Some(GamePrintGuides::Superboard)
-                        if board_row == 1 && superboard_column_idx == 0 =>
-                    {
-                        print!("{} ", superboard_row_idx + 1);
-                    }
-                    _ => print!("  "),
-                }
-
-                let board = superboard_row[superboard_column_idx];
-                let row = &board[board_row * 3..][..3];
-                let disp = |i: usize| row[i].unwrap_or(b'-') as char;
-                print!("{} {} {}", disp(0), disp(1), disp(2));
-            }
-            println!();
-        }
-    }
+    print!(
+        "{}",
+        sized::fmt_grid(3, |board, square| superboard[board][square], guides)
+    );
 }
 
 /// Convert a coordinate to it's character representation
-fn coord_to_chars(c: usize) -> [char; 2] {
+pub(crate) fn coord_to_chars(c: usize) -> [char; 2] {
     [
-        ((c % 3) as u8 + 'A' as u8) as char,
-        ((c / 3) as u8 + '1' as u8) as char,
+        ((c % 3) as u8 + b'A') as char,
+        ((c / 3) as u8 + b'1') as char,
     ]
 }
 
@@ -295,6 +566,61 @@ pub fn fmt_move(mov: Move) -> String {
     s
 }
 
+/// Reasons `parse_move` may reject its input
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ParseError {
+    /// Expected a column A-C and a row 1-3, in either order
+    InvalidCoord(String),
+    /// Expected either `B2>A1` or `A1`
+    InvalidMove(String),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::InvalidCoord(s) => write!(f, "invalid coordinate \"{}\"", s),
+            ParseError::InvalidMove(s) => write!(f, "invalid move \"{}\"", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse a coordinate such as `B2` (or `2B`, `b2`) into a row-major index
+pub fn parse_coord(s: &str) -> Result<usize, ParseError> {
+    let err = || ParseError::InvalidCoord(s.to_string());
+    let &[a, b] = s.as_bytes() else {
+        return Err(err());
+    };
+
+    let (col, row) = match (a.to_ascii_uppercase(), b) {
+        (col @ b'A'..=b'C', row @ b'1'..=b'3') => (col, row),
+        (row @ b'1'..=b'3', col) => match col.to_ascii_uppercase() {
+            col @ b'A'..=b'C' => (col, row),
+            _ => return Err(err()),
+        },
+        _ => return Err(err()),
+    };
+
+    Ok(((row - b'1') * 3 + (col - b'A')) as usize)
+}
+
+/// Parse a move in the notation written by `fmt_move`, either `B2>A1` or `A1`
+pub fn parse_move(s: &str) -> Result<Move, ParseError> {
+    let s = s.trim();
+    match s.split('>').collect::<Vec<_>>()[..] {
+        [board] => Ok(Move {
+            superboard: None,
+            board: parse_coord(board)?,
+        }),
+        [superboard, board] => Ok(Move {
+            superboard: Some(parse_coord(superboard)?),
+            board: parse_coord(board)?,
+        }),
+        _ => Err(ParseError::InvalidMove(s.to_string())),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -332,7 +658,10 @@ mod tests {
             board_shorthand(*b"XXX-O-O--"),
         ];
 
-        assert_eq!(is_superboard_won(&superboard), Some(b'X'));
+        assert_eq!(
+            is_superboard_won(&superboard, &RuleSet::default()),
+            Some(b'X')
+        );
 
         let superboard = [
             board_shorthand(*b"XXX-O-O--"),
@@ -346,6 +675,348 @@ mod tests {
             board_shorthand(*b"X-X-O-O--"),
         ];
 
-        assert!(is_superboard_won(&superboard).is_none());
+        assert!(is_superboard_won(&superboard, &RuleSet::default()).is_none());
+    }
+
+    #[test]
+    fn test_try_apply_move() {
+        let state = GameState::new(b"XO", RuleSet::default());
+        let mov = |superboard, board| Move { superboard, board };
+
+        assert_eq!(
+            state.try_apply_move(mov(None, 4)),
+            Err(MoveError::MissingSuperboard)
+        );
+        assert_eq!(
+            state.try_apply_move(mov(Some(9), 4)),
+            Err(MoveError::OutOfRange(9))
+        );
+        assert_eq!(
+            state.try_apply_move(mov(Some(4), 12)),
+            Err(MoveError::OutOfRange(12))
+        );
+
+        // X plays the centre of the centre, so O is sent back to the centre
+        let state = state.try_apply_move(mov(Some(4), 4)).unwrap();
+        assert_eq!(state.sent_to(), Some(4));
+        assert_eq!(
+            state.try_apply_move(mov(Some(4), 0)),
+            Err(MoveError::UnexpectedSuperboard)
+        );
+        assert_eq!(
+            state.try_apply_move(mov(None, 4)),
+            Err(MoveError::Occupied {
+                board: 4,
+                square: 4
+            })
+        );
+
+        let mut superboard = EMPTY_SUPERBOARD;
+        superboard[0] = board_shorthand(*b"XXX-O-O--");
+        let state = GameState::new(b"XO", RuleSet::default()).with_superboard(superboard);
+        assert_eq!(
+            state.try_apply_move(mov(Some(0), 3)),
+            Err(MoveError::BoardWon { board: 0 })
+        );
+
+        let superboard = [board_shorthand(*b"XXX------"); 9];
+        let state = GameState::new(b"XO", RuleSet::default()).with_superboard(superboard);
+        assert_eq!(
+            state.try_apply_move(mov(Some(0), 3)),
+            Err(MoveError::GameOver)
+        );
+    }
+
+    #[test]
+    fn test_invalid_state() {
+        let state = GameState::new(b"XO", RuleSet::default());
+        let mov = Move {
+            superboard: Some(4),
+            board: 4,
+        };
+        let with_next = |next_to_play| {
+            GameState::from_parts(
+                EMPTY_SUPERBOARD,
+                [None; 9],
+                state.players(),
+                next_to_play,
+                None,
+                state.rules(),
+            )
+        };
+        for broken in [GameState::new(b"", RuleSet::default()), with_next(2)] {
+            assert_eq!(broken.try_apply_move(mov), Err(MoveError::InvalidState));
+            let bits = bitboard::BitGameState::from(&broken);
+            assert_eq!(bits.try_apply_move(mov), Err(MoveError::InvalidState));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_apply_move_panics() {
+        GameState::new(b"XO", RuleSet::default()).apply_move(Move {
+            superboard: None,
+            board: 0,
+        });
+    }
+
+    #[test]
+    fn test_outcome() {
+        let state = GameState::new(b"XO", RuleSet::default());
+        assert_eq!(state.outcome(), GameOutcome::Ongoing);
+
+        let x = board_shorthand(*b"XXX------");
+        let o = board_shorthand(*b"OOO------");
+        let tied = board_shorthand(*b"XOXXOOOXX");
+        let dead = board_shorthand(*b"XOXXOOOX-");
+        let empty = EMPTY_BOARD;
+
+        let with =
+            |superboard| GameState::new(b"XO", RuleSet::default()).with_superboard(superboard);
+
+        assert_eq!(
+            with([x, x, x, o, o, empty, empty, empty, empty]).outcome(),
+            GameOutcome::Won(b'X')
+        );
+
+        // Every board is decided and nobody has a line
+        assert_eq!(
+            with([x, o, x, x, tied, o, o, x, o]).outcome(),
+            GameOutcome::Draw
+        );
+
+        // The centre is open, but every line through it is already blocked
+        assert_eq!(
+            with([x, o, x, x, empty, o, o, x, o]).outcome(),
+            GameOutcome::Draw
+        );
+
+        // Both players can still win through the centre...
+        assert_eq!(
+            with([o, x, x, x, empty, x, o, x, o]).outcome(),
+            GameOutcome::Ongoing
+        );
+
+        // ...unless nobody can win the centre board itself
+        let state = with([o, x, x, x, dead, x, o, x, o]);
+        assert_eq!(state.outcome(), GameOutcome::Draw);
+        assert!(successors(&state).is_empty());
+    }
+
+    #[test]
+    fn test_play_into_won_boards() {
+        let rules = RuleSet {
+            play_into_won_boards: true,
+            ..RuleSet::STANDARD
+        };
+        let mov = |superboard, board| Move { superboard, board };
+
+        let mut superboard = EMPTY_SUPERBOARD;
+        superboard[0] = board_shorthand(*b"XXX-O-O--");
+        superboard[1] = board_shorthand(*b"---O-----");
+        let state = GameState::new(b"XO", rules).with_superboard(superboard);
+        assert_eq!(state.board_winner(0), Some(b'X'));
+
+        // A won board can still be picked, and sends the next player there
+        let state = state.apply_move(mov(Some(1), 0));
+        assert_eq!(state.sent_to(), Some(0));
+        assert_eq!(state.count_legal_moves(), 4);
+
+        // O completes a line on X's board, but X keeps it
+        let state = state.apply_move(mov(None, 8));
+        assert_eq!(state.superboard[0], board_shorthand(*b"XXX-O-O-O"));
+        assert_eq!(state.board_winner(0), Some(b'X'));
+
+        let standard = GameState::new(b"XO", RuleSet::STANDARD).with_superboard(superboard);
+        assert_eq!(standard.apply_move(mov(Some(1), 0)).sent_to(), None);
+    }
+
+    #[test]
+    fn test_ties_count_for_all() {
+        let rules = RuleSet {
+            ties_count_for_all: true,
+            ..RuleSet::STANDARD
+        };
+        let x = board_shorthand(*b"XXX------");
+        let tied = board_shorthand(*b"XOXXOOOXX");
+        let empty = EMPTY_BOARD;
+        let superboard = [x, tied, tied, empty, empty, empty, empty, empty, empty];
+
+        let state = GameState::new(b"XO", rules).with_superboard(superboard);
+        assert_eq!(state.outcome(), GameOutcome::Won(b'X'));
+        assert_eq!(is_superboard_won(&superboard, &rules), Some(b'X'));
+        assert_eq!(is_superboard_won(&superboard, &RuleSet::STANDARD), None);
+        let o = board_shorthand(*b"OOO------");
+
+        // Every line holds a tie, but X can still complete one by winning the last open board
+        let open = board_shorthand(*b"XX-OOXXOO");
+        let superboard = [x, tied, open, o, o, x, o, x, tied];
+        let standard = GameState::new(b"XO", RuleSet::STANDARD).with_superboard(superboard);
+        assert_eq!(standard.outcome(), GameOutcome::Draw);
+        let state = GameState::new(b"XO", rules).with_superboard(superboard);
+        assert_eq!(state.outcome(), GameOutcome::Ongoing);
+    }
+
+    #[test]
+    fn test_centre_first_move() {
+        let rules = RuleSet {
+            centre_first_move: true,
+            ..RuleSet::STANDARD
+        };
+        let state = GameState::new(b"XO", rules);
+        let moves = state.legal_moves();
+        assert_eq!(moves.len(), 9);
+        assert!(moves.iter().all(|mov| mov.superboard == Some(4)));
+        assert_eq!(
+            state.try_apply_move(Move {
+                superboard: Some(0),
+                board: 0
+            }),
+            Err(MoveError::NotPlayable { board: 0 })
+        );
+        assert_eq!(
+            GameState::new(b"XO", RuleSet::STANDARD).count_legal_moves(),
+            81
+        );
+    }
+
+    #[test]
+    fn test_legal_moves() {
+        let mut state = GameState::new(b"XO", RuleSet::default());
+        while let Some(&mov) = state.legal_moves().last() {
+            let moves = state.legal_moves();
+            assert_eq!(state.count_legal_moves(), moves.len());
+            for (n, &mov) in moves.iter().enumerate() {
+                assert_eq!(state.nth_legal_move(n), Some(mov));
+            }
+            assert_eq!(state.nth_legal_move(moves.len()), None);
+
+            state = state.apply_move(mov);
+        }
+        assert_eq!(state.count_legal_moves(), 0);
+        assert_eq!(state.nth_legal_move(0), None);
+    }
+
+    #[test]
+    fn test_parse_move() {
+        let mov = |superboard, board| Move { superboard, board };
+        assert_eq!(parse_move("C3"), Ok(mov(None, 8)));
+        assert_eq!(parse_move("b2>a1"), Ok(mov(Some(4), 0)));
+        assert_eq!(parse_move("2B>1A"), Ok(mov(Some(4), 0)));
+        assert_eq!(parse_move(" A3>c1 "), Ok(mov(Some(6), 2)));
+        assert_eq!(parse_move("D1"), Err(ParseError::InvalidCoord("D1".into())));
+        assert_eq!(parse_move("A1>"), Err(ParseError::InvalidCoord("".into())));
+        assert_eq!(
+            parse_move("A1>B2>C3"),
+            Err(ParseError::InvalidMove("A1>B2>C3".into()))
+        );
+        assert!(parse_move("A12").is_err());
+        assert!(parse_move("").is_err());
+    }
+
+    #[test]
+    fn test_parse_move_round_trip() {
+        for superboard in (0..9).map(Some).chain([None]) {
+            for board in 0..9 {
+                let mov = Move { superboard, board };
+                let s = fmt_move(mov);
+                assert_eq!(parse_move(&s), Ok(mov));
+                assert_eq!(parse_move(&s.to_lowercase()), Ok(mov));
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut state = GameState::new(b"XO", RuleSet::default());
+        while let Some(mov) = crate::ai::random_move(state) {
+            let json = serde_json::to_string(&mov).unwrap();
+            assert_eq!(serde_json::from_str::<Move>(&json).unwrap(), mov);
+
+            state = state.apply_move(mov);
+            let json = serde_json::to_string(&state).unwrap();
+            assert_eq!(serde_json::from_str::<GameState>(&json).unwrap(), state);
+        }
+
+        for guides in [GamePrintGuides::Superboard, GamePrintGuides::Board(3)] {
+            let json = serde_json::to_string(&guides).unwrap();
+            assert_eq!(
+                serde_json::from_str::<GamePrintGuides>(&json).unwrap(),
+                guides
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_grid() {
+        let state = GameState::new(b"XO", RuleSet::default())
+            .apply_move(Move {
+                superboard: Some(1),
+                board: 4,
+            })
+            .apply_move(Move {
+                superboard: None,
+                board: 8,
+            });
+        let json = serde_json::to_value(state).unwrap();
+        assert_eq!(
+            json["superboard"],
+            serde_json::json!([
+                "---------",
+                "----X----",
+                "---------",
+                "---------",
+                "---------",
+                "-----O---",
+                "---------",
+                "---------",
+                "---------",
+            ])
+        );
+
+        assert_eq!(json["players"], "XO");
+
+        let mut bad_row = json.clone();
+        bad_row["superboard"][0] = "--".into();
+        assert!(serde_json::from_value::<GameState>(bad_row).is_err());
+
+        // Symbols above 127 are written as chars and read back the same way
+        let state = GameState::new(&[0xE9, b'O'], RuleSet::default()).apply_move(Move {
+            superboard: Some(0),
+            board: 0,
+        });
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("\"\u{e9}--------\""));
+        assert_eq!(serde_json::from_str::<GameState>(&json).unwrap(), state);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_checks() {
+        let state = GameState::new(b"XO", RuleSet::default()).apply_move(Move {
+            superboard: Some(1),
+            board: 4,
+        });
+        let json = serde_json::to_value(state).unwrap();
+        let with = |field: &str, value: serde_json::Value| {
+            let mut json = json.clone();
+            json[field] = value;
+            serde_json::from_value::<GameState>(json)
+        };
+
+        assert!(with("players", "".into()).is_err());
+        assert!(with("players", "XOABC".into()).is_err());
+        assert!(with("players", "XX".into()).is_err());
+        assert!(with("next_to_play", 2.into()).is_err());
+        assert!(with("sent_to", 9.into()).is_err());
+        // Sub-board 1 has no line, so it can't have a winner
+        let mut winners = json["board_winners"].clone();
+        winners[1] = b'X'.into();
+        assert!(with("board_winners", winners).is_err());
+        // O to move, so X has had one more turn than O
+        assert!(with("next_to_play", 0.into()).is_err());
+        assert_eq!(with("next_to_play", 1.into()).unwrap(), state);
     }
 }
@@ -0,0 +1,472 @@
+//! Ultimate tic-tac-toe nested to any depth: a depth 1 game is plain tic-tac-toe, depth 2 is the
+//! usual ultimate game, and depth 3 is a 3×3 grid of ultimate games. Played under the standard rules.
+//!
+//! Every level is a 3×3 grid, so a square is addressed by a path of `depth` row-major indices,
+//! outermost first. Moves are written as that path packed into a single base-9 number, the square's
+//! "cell". Boards at each level are identified by a `NodeId`; level 0 nodes are the squares themselves.
+//!
+//! Playing at path `[a, b, c]` sends the next player to the innermost board at path `[b, c]`,
+//! just as playing at `[a, b]` sends them to board `[b]` in the usual game. If that board is won
+//! or full, they are sent to the board one level up which contains it (`[b]`), and so on, up to a
+//! free choice of anywhere on the board.
+
+use crate::{GameOutcome, Player, Square, LINES, MAX_PLAYERS};
+
+/// Deepest nesting supported. Depth 5 already has 59049 squares.
+pub const MAX_DEPTH: usize = 5;
+
+/// A board at some level of a nested game
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    /// 0 for a single square, 1 for a 3×3 board of squares, and so on up to the game's depth
+    pub level: usize,
+    /// Index among the boards of this level, which are numbered row-major and outermost first
+    pub index: usize,
+}
+
+impl NodeId {
+    /// The board one level up which contains this one
+    pub fn parent(&self) -> NodeId {
+        NodeId {
+            level: self.level + 1,
+            index: self.index / 9,
+        }
+    }
+
+    /// The nine boards one level down within this one
+    pub fn children(&self) -> impl Iterator<Item = NodeId> {
+        let NodeId { level, index } = *self;
+        (0..9).map(move |i| NodeId {
+            level: level - 1,
+            index: index * 9 + i,
+        })
+    }
+
+    /// The range of cells covered by this board
+    pub fn cells(&self) -> std::ops::Range<usize> {
+        let size = 9usize.pow(self.level as u32);
+        self.index * size..(self.index + 1) * size
+    }
+}
+
+/// Reasons a move may be rejected by `NestedGameState::try_apply_move`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NestedMoveError {
+    /// The game is already over
+    GameOver,
+    /// The cell is past the end of the board
+    OutOfRange(usize),
+    /// The square is already taken
+    Occupied(usize),
+    /// The given board, which holds the cell, is already won or full
+    Closed(NodeId),
+    /// The player was sent to the given board, and the cell is outside it
+    NotSentTo(NodeId),
+}
+
+impl std::fmt::Display for NestedMoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NestedMoveError::GameOver => write!(f, "the game is already over"),
+            NestedMoveError::OutOfRange(cell) => write!(f, "cell {} is out of range", cell),
+            NestedMoveError::Occupied(cell) => write!(f, "cell {} is occupied", cell),
+            NestedMoveError::Closed(node) => write!(
+                f,
+                "board {} at level {} is already won or full",
+                node.index, node.level
+            ),
+            NestedMoveError::NotSentTo(node) => write!(
+                f,
+                "the player was sent to board {} at level {}",
+                node.index, node.level
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NestedMoveError {}
+
+/// A single game state, nested to any depth
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct NestedGameState {
+    /// Number of levels of boards
+    pub depth: usize,
+    /// `winners[0]` holds the squares. `winners[level][index]` is the winner of that board,
+    /// so `winners[depth][0]` is the winner of the game.
+    pub winners: Vec<Vec<Square>>,
+    /// Whether each board is won or full, laid out as `winners`
+    pub closed: Vec<Vec<bool>>,
+    /// The players in this game, ordered by who goes first.
+    pub players: [Player; MAX_PLAYERS],
+    /// Index of the player from `players` who will make the next move
+    pub next_to_play: usize,
+    /// If any, the board the player has been sent to
+    pub sent_to: Option<NodeId>,
+    /// Number of players
+    pub num_players: usize,
+}
+
+impl NestedGameState {
+    /// Create a new game given player symbols and the number of levels of boards
+    pub fn new(players: &[Player], depth: usize) -> Self {
+        assert!(
+            (1..=MAX_DEPTH).contains(&depth),
+            "depth ({}) must be between 1 and {}.",
+            depth,
+            MAX_DEPTH
+        );
+        assert!(
+            players.len() <= MAX_PLAYERS,
+            "MAX_PLAYERS ({}) exceeded.",
+            MAX_PLAYERS
+        );
+        let mut players_array = [b'*'; MAX_PLAYERS];
+        players_array[..players.len()].copy_from_slice(players);
+
+        let sizes = (0..=depth).map(|level| 9usize.pow((depth - level) as u32));
+        Self {
+            depth,
+            winners: sizes.clone().map(|size| vec![None; size]).collect(),
+            closed: sizes.map(|size| vec![false; size]).collect(),
+            players: players_array,
+            next_to_play: 0,
+            sent_to: None,
+            num_players: players.len(),
+        }
+    }
+
+    /// Returns the symbol of the next player to play
+    pub fn next_to_play(&self) -> Player {
+        self.players[self.next_to_play]
+    }
+
+    /// Returns the symbols of the players in this game, ordered by who goes first
+    pub fn players(&self) -> &[Player] {
+        &self.players[..self.num_players]
+    }
+
+    /// The whole board
+    pub fn root(&self) -> NodeId {
+        NodeId {
+            level: self.depth,
+            index: 0,
+        }
+    }
+
+    /// Number of squares on the board
+    pub fn num_cells(&self) -> usize {
+        self.winners[0].len()
+    }
+
+    /// Returns the winner of the given board, or the owner of the given square at level 0
+    pub fn winner(&self, node: NodeId) -> Option<Player> {
+        self.winners[node.level][node.index]
+    }
+
+    /// Returns true if the given board, or any board containing it, is won or full.
+    /// The outermost board is left out, since closing it ends the game.
+    pub fn is_closed(&self, mut node: NodeId) -> bool {
+        while node.level < self.depth {
+            if self.closed[node.level][node.index] {
+                return true;
+            }
+            node = node.parent();
+        }
+        false
+    }
+
+    /// Returns whether the game has been won, drawn, or is still in progress.
+    /// A game is drawn early once no player can complete a line on the outermost board.
+    pub fn outcome(&self) -> GameOutcome {
+        if let Some(winner) = self.winner(self.root()) {
+            return GameOutcome::Won(winner);
+        }
+
+        if self.players().iter().any(|&p| self.can_be_won_by(p)) {
+            GameOutcome::Ongoing
+        } else {
+            GameOutcome::Draw
+        }
+    }
+
+    /// Returns true if the given player could still win the game, working up from the squares
+    fn can_be_won_by(&self, player: Player) -> bool {
+        let mut winnable: Vec<bool> = self.winners[0]
+            .iter()
+            .map(|square| square.map_or(true, |p| p == player))
+            .collect();
+
+        for level in 1..=self.depth {
+            winnable = (0..self.winners[level].len())
+                .map(|index| match self.winners[level][index] {
+                    Some(winner) => winner == player,
+                    None if self.closed[level][index] => false,
+                    None => LINES
+                        .iter()
+                        .any(|line| line.iter().all(|&i| winnable[index * 9 + i])),
+                })
+                .collect();
+        }
+
+        winnable[0]
+    }
+
+    /// Return the legal moves from this state, as cells. Will be empty if the game is finished.
+    pub fn legal_moves(&self) -> Vec<usize> {
+        if self.outcome() != GameOutcome::Ongoing {
+            return vec![];
+        }
+
+        self.sent_to
+            .unwrap_or(self.root())
+            .cells()
+            .filter(|&cell| self.winners[0][cell].is_none() && !self.is_closed(cell_board(cell)))
+            .collect()
+    }
+
+    /// Apply a move to this state. Panics if the move is invalid; see `try_apply_move`
+    pub fn apply_move(&self, cell: usize) -> Self {
+        self.try_apply_move(cell)
+            .unwrap_or_else(|e| panic!("Illegal move {}: {}", fmt_nested_move(cell, self.depth), e))
+    }
+
+    /// Apply a move to this state, returning an error instead if the move is invalid
+    pub fn try_apply_move(&self, cell: usize) -> Result<Self, NestedMoveError> {
+        if self.outcome() != GameOutcome::Ongoing {
+            return Err(NestedMoveError::GameOver);
+        }
+        if cell >= self.num_cells() {
+            return Err(NestedMoveError::OutOfRange(cell));
+        }
+        if let Some(sent_to) = self.sent_to {
+            if !sent_to.cells().contains(&cell) {
+                return Err(NestedMoveError::NotSentTo(sent_to));
+            }
+        }
+        if self.winners[0][cell].is_some() {
+            return Err(NestedMoveError::Occupied(cell));
+        }
+        let mut node = cell_board(cell);
+        while node.level <= self.depth {
+            if self.closed[node.level][node.index] {
+                return Err(NestedMoveError::Closed(node));
+            }
+            node = node.parent();
+        }
+
+        // Make the move, then work outwards through the boards containing it
+        let mut next = self.clone();
+        next.winners[0][cell] = Some(self.next_to_play());
+        next.closed[0][cell] = true;
+
+        let mut node = cell_board(cell);
+        while node.level <= self.depth {
+            let children: Vec<NodeId> = node.children().collect();
+            let winner = LINES.iter().find_map(|line| {
+                let player = next.winner(children[line[0]])?;
+                line.iter()
+                    .all(|&i| next.winner(children[i]) == Some(player))
+                    .then_some(player)
+            });
+            next.winners[node.level][node.index] = winner;
+            next.closed[node.level][node.index] = winner.is_some()
+                || children
+                    .iter()
+                    .all(|child| next.closed[child.level][child.index]);
+            node = node.parent();
+        }
+
+        // Send the next player to the board with the same path as the move, less its outermost index.
+        // If that is closed, widen to the board which contains it, and so on.
+        let inner = cell % (self.num_cells() / 9);
+        next.sent_to = (1..self.depth)
+            .map(|level| NodeId {
+                level,
+                index: inner / 9usize.pow(level as u32 - 1),
+            })
+            .find(|&node| !next.is_closed(node));
+
+        // Calculate the next player
+        next.next_to_play = (self.next_to_play + 1) % self.num_players;
+
+        Ok(next)
+    }
+
+    /// Lay out the board as text. Boards at each level are separated by one more space
+    /// (and, above the first level, one more blank line) than the boards inside them.
+    pub fn fmt_board(&self) -> String {
+        render(self, self.root()).join("\n") + "\n"
+    }
+}
+
+/// The innermost 3×3 board containing a cell
+fn cell_board(cell: usize) -> NodeId {
+    NodeId {
+        level: 1,
+        index: cell / 9,
+    }
+}
+
+/// Lines of text showing the given board
+fn render(state: &NestedGameState, node: NodeId) -> Vec<String> {
+    if node.level == 0 {
+        return vec![(state.winners[0][node.index].unwrap_or(b'-') as char).to_string()];
+    }
+
+    let children: Vec<Vec<String>> = node.children().map(|child| render(state, child)).collect();
+    let gap = " ".repeat(node.level);
+    let mut lines = vec![];
+    for (row_idx, row) in children.chunks_exact(3).enumerate() {
+        if row_idx > 0 {
+            lines.extend(std::iter::repeat(String::new()).take(node.level - 1));
+        }
+        for line_idx in 0..row[0].len() {
+            let parts: Vec<&str> = row.iter().map(|child| child[line_idx].as_str()).collect();
+            lines.push(parts.join(&gap));
+        }
+    }
+    lines
+}
+
+/// Returns the path of row-major indices, outermost first, leading to a cell
+pub fn cell_path(cell: usize, depth: usize) -> Vec<usize> {
+    (0..depth)
+        .rev()
+        .map(|level| cell / 9usize.pow(level as u32) % 9)
+        .collect()
+}
+
+/// Display a move as its path of coordinates, outermost first, e.g. `B2>A1>C3`
+pub fn fmt_nested_move(cell: usize, depth: usize) -> String {
+    let coords: Vec<String> = cell_path(cell, depth)
+        .into_iter()
+        .map(|idx| crate::coord_to_chars(idx).iter().collect())
+        .collect();
+    coords.join(">")
+}
+
+/// Print the given game state
+pub fn print_nested_game_state(state: &NestedGameState) {
+    match state.outcome() {
+        GameOutcome::Won(winner) => println!("{} won.", winner as char),
+        GameOutcome::Draw => println!("Draw."),
+        GameOutcome::Ongoing => println!("{} to play.", state.next_to_play() as char),
+    }
+
+    print!("{}", state.fmt_board());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GameState, Move, RuleSet};
+    use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_matches_game_state() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for players in [&b"XO"[..], b"XOA"] {
+            for _ in 0..30 {
+                let mut state = GameState::new(players, RuleSet::STANDARD);
+                let mut nested = NestedGameState::new(players, 2);
+                loop {
+                    assert_eq!(nested.outcome(), state.outcome());
+                    assert_eq!(nested.sent_to.map(|node| node.index), state.sent_to());
+
+                    let moves: Vec<usize> = state
+                        .legal_moves()
+                        .iter()
+                        .map(|mov| 9 * mov.superboard.or(state.sent_to()).unwrap() + mov.board)
+                        .collect();
+                    assert_eq!(nested.legal_moves(), moves);
+
+                    let Some(&cell) = moves.choose(&mut rng) else {
+                        break;
+                    };
+                    state = state.apply_move(Move {
+                        superboard: state.sent_to().is_none().then_some(cell / 9),
+                        board: cell % 9,
+                    });
+                    nested = nested.apply_move(cell);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_depth_one() {
+        let mut state = NestedGameState::new(b"XO", 1);
+        for (ply, cell) in [4, 0, 2, 6, 3, 5, 7, 1].into_iter().enumerate() {
+            assert_eq!(state.sent_to, None);
+            assert_eq!(state.legal_moves().len(), 9 - ply);
+            state = state.apply_move(cell);
+        }
+        // Nobody can complete a line with only square 8 left
+        assert_eq!(state.outcome(), GameOutcome::Draw);
+    }
+
+    #[test]
+    fn test_depth_three() {
+        let path = |path: [usize; 3]| path.iter().fold(0, |cell, idx| cell * 9 + idx);
+        let state = NestedGameState::new(b"XO", 3);
+        assert_eq!(state.legal_moves().len(), 729);
+
+        // Playing at [4, 0, 8] sends O to board [0, 8]
+        let state = state.apply_move(path([4, 0, 8]));
+        let board = NodeId { level: 1, index: 8 };
+        assert_eq!(state.sent_to, Some(board));
+        assert_eq!(state.legal_moves(), board.cells().collect::<Vec<_>>());
+        assert_eq!(
+            state.try_apply_move(path([4, 0, 0])),
+            Err(NestedMoveError::NotSentTo(board))
+        );
+        assert_eq!(fmt_nested_move(path([4, 0, 8]), 3), "B2>A1>C3");
+        assert_eq!(cell_path(path([4, 0, 8]), 3), vec![4, 0, 8]);
+
+        // X wins the middle row of board [0, 0], taking two moves each time to be sent back there
+        let mut state = NestedGameState::new(b"XO", 3);
+        for (square, away) in [(3, 1), (4, 2), (5, 5)] {
+            state = state.apply_move(path([0, 0, square]));
+            if square == 5 {
+                break;
+            }
+            state = state
+                .apply_move(path([0, square, away]))
+                .apply_move(path([square, away, 0]))
+                .apply_move(path([away, 0, 0]));
+            assert_eq!(state.sent_to, Some(NodeId { level: 1, index: 0 }));
+        }
+        let won = NodeId { level: 1, index: 0 };
+        assert_eq!(state.winner(won), Some(b'X'));
+        assert_eq!(state.outcome(), GameOutcome::Ongoing);
+
+        // Sending X back to the won board [0, 0] lets them play anywhere else in [0]
+        let state = state
+            .apply_move(path([0, 5, 6]))
+            .apply_move(path([5, 6, 0]))
+            .apply_move(path([6, 0, 0]));
+        assert_eq!(state.sent_to, Some(NodeId { level: 2, index: 0 }));
+        assert_eq!(
+            state.try_apply_move(path([0, 0, 0])),
+            Err(NestedMoveError::Closed(won))
+        );
+        // 81 squares, less the won board and the three pieces played elsewhere in [0]
+        assert_eq!(state.legal_moves().len(), 81 - 9 - 3);
+    }
+
+    #[test]
+    fn test_fmt_board() {
+        let text = NestedGameState::new(b"XO", 2).apply_move(4).fmt_board();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], "- - -  - - -  - - -");
+        assert_eq!(lines[1], "- X -  - - -  - - -");
+        assert_eq!(lines[3], "");
+
+        let state = NestedGameState::new(b"XO", 3);
+        let text = state.fmt_board();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 27 + 2 * 2 + 3 * 2);
+        assert_eq!(lines[0].len(), 63);
+    }
+}
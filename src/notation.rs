@@ -0,0 +1,464 @@
+//! A single-line notation for game states, in the spirit of chess FEN.
+//!
+//! ```text
+//! ---------/----X----/---------/---------/-O-------/---------/---------/---------/--------- XO X B1
+//! ```
+//!
+//! The fields, separated by spaces, are:
+//! 1. The nine sub-boards in superboard order, separated by `/`. Each lists its squares
+//!    in row-major order, with `-` for an empty square.
+//! 2. The player symbols, in turn order.
+//! 3. The symbol of the player to move.
+//! 4. The sub-board the player to move was sent to (e.g. `B1`), or `-` for a free choice.
+//! 5. Optionally, the rule variant as written by `RuleSet`'s `Display`. Omitted for the standard rules.
+//!
+//! Under `RuleSet::play_into_won_boards` a sub-board can hold lines for more than one player.
+//! Such boards are prefixed with the symbol of the player who won them first, as in `X=XXXOOO---`.
+//!
+//! Player symbols are bytes, written as the char with the same value, so `0xE9` is `é`.
+
+use crate::{
+    coord_to_chars, parse_coord, Board, GameState, Player, RuleSet, SuperBoard, EMPTY_SUPERBOARD,
+    LINES, MAX_PLAYERS,
+};
+
+/// Problems found while reading a position
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NotationError {
+    /// Expected four or five space-separated fields
+    WrongFieldCount(usize),
+    /// The rule variant was not recognised
+    UnknownRules(String),
+    /// Expected nine sub-boards of nine squares each
+    BadBoards,
+    /// The player list was empty, too long, repeated a symbol or used a reserved character
+    BadPlayers(String),
+    /// A square holds a symbol which is not in the player list
+    UnknownSymbol { board: usize, square: usize },
+    /// A sub-board's winner is missing, or does not have a line on it
+    BadWinner(usize),
+    /// The player to move is not in the player list
+    BadNextToPlay(String),
+    /// The sent-to field is not a coordinate or `-`
+    BadSentTo(String),
+    /// The sent-to board is already won or full
+    SentToClosedBoard(usize),
+    /// The number of pieces each player has does not match whose turn it is
+    PieceCounts,
+}
+
+impl std::fmt::Display for NotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotationError::WrongFieldCount(n) => write!(f, "expected 4 or 5 fields, found {}", n),
+            NotationError::UnknownRules(s) => write!(f, "unknown rules \"{}\"", s),
+            NotationError::BadBoards => write!(f, "expected 9 boards of 9 squares"),
+            NotationError::BadPlayers(s) => write!(f, "bad player list \"{}\"", s),
+            NotationError::UnknownSymbol { board, square } => {
+                write!(f, "unknown symbol in square {} of board {}", square, board)
+            }
+            NotationError::BadWinner(board) => write!(f, "bad winner for board {}", board),
+            NotationError::BadNextToPlay(s) => write!(f, "bad player to move \"{}\"", s),
+            NotationError::BadSentTo(s) => write!(f, "bad sent-to board \"{}\"", s),
+            NotationError::SentToClosedBoard(b) => {
+                write!(f, "sent to board {}, which is already closed", b)
+            }
+            NotationError::PieceCounts => {
+                write!(f, "piece counts do not match the player to move")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+impl GameState {
+    /// Write this state in the single-line position notation. Fails if a player's symbol is one
+    /// the notation reserves, since it could not be read back.
+    pub fn to_notation(&self) -> Result<String, NotationError> {
+        check_players(self.players())?;
+
+        let boards: Vec<String> = self
+            .superboard()
+            .iter()
+            .enumerate()
+            .map(|(board_idx, board)| {
+                let squares: String = board
+                    .iter()
+                    .map(|square| char::from(square.unwrap_or(b'-')))
+                    .collect();
+                match self.board_winner(board_idx) {
+                    Some(winner) if line_owners(board).len() > 1 => {
+                        format!("{}={}", char::from(winner), squares)
+                    }
+                    _ => squares,
+                }
+            })
+            .collect();
+
+        let players: String = self.players().iter().map(|&p| char::from(p)).collect();
+
+        let sent_to = match self.sent_to() {
+            Some(idx) => coord_to_chars(idx).iter().collect(),
+            None => "-".to_string(),
+        };
+
+        let mut notation = format!(
+            "{} {} {} {}",
+            boards.join("/"),
+            players,
+            char::from(self.next_to_play()),
+            sent_to
+        );
+        if self.rules() != RuleSet::STANDARD {
+            notation.push(' ');
+            notation.push_str(&self.rules().to_string());
+        }
+        Ok(notation)
+    }
+
+    /// Read a state from the single-line position notation, checking that it is consistent
+    pub fn from_notation(s: &str) -> Result<Self, NotationError> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let (boards, players, next, sent_to, rules) = match fields[..] {
+            [boards, players, next, sent_to] => (boards, players, next, sent_to, None),
+            [boards, players, next, sent_to, rules] => {
+                (boards, players, next, sent_to, Some(rules))
+            }
+            _ => return Err(NotationError::WrongFieldCount(fields.len())),
+        };
+        let rules = match rules {
+            Some(rules) => rules
+                .parse()
+                .map_err(|_| NotationError::UnknownRules(rules.to_string()))?,
+            None => RuleSet::STANDARD,
+        };
+
+        // Players
+        let symbols =
+            to_symbols(players).ok_or_else(|| NotationError::BadPlayers(players.to_string()))?;
+        check_players(&symbols)?;
+
+        // Boards, each optionally prefixed by its winner
+        let boards: Vec<(Option<u8>, Vec<u8>)> = boards
+            .split('/')
+            .map(|board| match to_symbols(board).as_deref() {
+                Some([winner, b'=', squares @ ..]) => (Some(*winner), squares.to_vec()),
+                Some(squares) => (None, squares.to_vec()),
+                None => (None, Vec::new()),
+            })
+            .collect();
+        if boards.len() != 9 || boards.iter().any(|(_, b)| b.len() != 9) {
+            return Err(NotationError::BadBoards);
+        }
+        let mut superboard = EMPTY_SUPERBOARD;
+        let mut board_winners = [None; 9];
+        for (board_idx, (winner, board)) in boards.into_iter().enumerate() {
+            for (square, c) in board.into_iter().enumerate() {
+                superboard[board_idx][square] = (c != b'-').then_some(c);
+            }
+            // The winner only needs writing out when more than one player has a line
+            board_winners[board_idx] =
+                winner.or_else(|| match line_owners(&superboard[board_idx])[..] {
+                    [owner] => Some(owner),
+                    _ => None,
+                });
+        }
+
+        // Player to move
+        let next_to_play = match to_symbols(next).as_deref() {
+            Some([c]) => symbols.iter().position(|p| p == c),
+            _ => None,
+        }
+        .ok_or_else(|| NotationError::BadNextToPlay(next.to_string()))?;
+
+        // Sent to
+        let sent_to = match sent_to {
+            "-" => None,
+            coord => {
+                Some(parse_coord(coord).map_err(|_| NotationError::BadSentTo(coord.to_string()))?)
+            }
+        };
+
+        GameState::from_checked_parts(
+            superboard,
+            board_winners,
+            &symbols,
+            next_to_play,
+            sent_to,
+            rules,
+        )
+    }
+
+    /// Build a state from its parts, checking that they are consistent in the same way as
+    /// `from_notation`. Used wherever a state is read in rather than played to.
+    pub(crate) fn from_checked_parts(
+        superboard: SuperBoard,
+        board_winners: [Option<Player>; 9],
+        players: &[Player],
+        next_to_play: usize,
+        sent_to: Option<usize>,
+        rules: RuleSet,
+    ) -> Result<Self, NotationError> {
+        check_players(players)?;
+
+        for (board_idx, board) in superboard.iter().enumerate() {
+            for (square, cell) in board.iter().enumerate() {
+                if cell.is_some_and(|c| !players.contains(&c)) {
+                    return Err(NotationError::UnknownSymbol {
+                        board: board_idx,
+                        square,
+                    });
+                }
+            }
+
+            // The winner must have a line, and a board with a line must have a winner
+            let owners = line_owners(board);
+            let consistent = match board_winners[board_idx] {
+                Some(winner) => owners.contains(&winner),
+                None => owners.is_empty(),
+            };
+            if !consistent {
+                return Err(NotationError::BadWinner(board_idx));
+            }
+        }
+
+        if next_to_play >= players.len() {
+            return Err(NotationError::BadNextToPlay(next_to_play.to_string()));
+        }
+
+        // Each player moves once per round, so the players before the one to move
+        // have exactly one more piece than the rest
+        let counts: Vec<usize> = players
+            .iter()
+            .map(|&p| count_pieces(&superboard, p))
+            .collect();
+        let total: usize = counts.iter().sum();
+        let (rounds, extra) = (total / players.len(), total % players.len());
+        let expected = (0..players.len()).map(|i| rounds + usize::from(i < extra));
+        if extra != next_to_play || !expected.eq(counts.iter().copied()) {
+            return Err(NotationError::PieceCounts);
+        }
+
+        let state = GameState::from_parts(
+            superboard,
+            board_winners,
+            players,
+            next_to_play,
+            None,
+            rules,
+        );
+        match sent_to {
+            None => Ok(state),
+            Some(idx) if idx >= 9 => Err(NotationError::BadSentTo(idx.to_string())),
+            Some(idx) if state.open_squares(idx).next().is_none() => {
+                Err(NotationError::SentToClosedBoard(idx))
+            }
+            Some(idx) => Ok(state.with_sent_to(Some(idx))),
+        }
+    }
+}
+
+/// The player list must be non-empty, not too long, have no repeats and avoid reserved characters:
+/// `-` for an empty square, `/` between boards, `=` after a board's winner, and whitespace
+/// between fields
+fn check_players(players: &[Player]) -> Result<(), NotationError> {
+    let unique = players
+        .iter()
+        .enumerate()
+        .all(|(i, p)| !players[..i].contains(p));
+    let reserved = |&p: &Player| b"-/=".contains(&p) || char::from(p).is_whitespace();
+    if players.is_empty() || players.len() > MAX_PLAYERS || !unique || players.iter().any(reserved)
+    {
+        return Err(NotationError::BadPlayers(
+            players.iter().map(|&p| char::from(p)).collect(),
+        ));
+    }
+    Ok(())
+}
+
+/// Read symbols written as the char with the same value, as `to_notation` writes them
+pub(crate) fn to_symbols(s: &str) -> Option<Vec<Player>> {
+    s.chars().map(|c| Player::try_from(c).ok()).collect()
+}
+
+/// Every player with a line on this board
+fn line_owners(board: &Board) -> Vec<Player> {
+    let mut owners: Vec<Player> = LINES
+        .iter()
+        .filter_map(|line| {
+            let player = board[line[0]]?;
+            line.iter()
+                .all(|&idx| board[idx] == Some(player))
+                .then_some(player)
+        })
+        .collect();
+    owners.sort_unstable();
+    owners.dedup();
+    owners
+}
+
+fn count_pieces(superboard: &SuperBoard, player: Player) -> usize {
+    superboard
+        .iter()
+        .flatten()
+        .filter(|&&square| square == Some(player))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ai::random_move, Move, RuleSet};
+
+    #[test]
+    fn test_notation() {
+        let state = GameState::new(b"XO", RuleSet::default())
+            .apply_move(Move {
+                superboard: Some(1),
+                board: 4,
+            })
+            .apply_move(Move {
+                superboard: None,
+                board: 1,
+            });
+        let notation = "---------/----X----/---------/---------/-O-------/---------/---------/---------/--------- XO X B1";
+        assert_eq!(state.to_notation(), Ok(notation.to_string()));
+        assert_eq!(GameState::from_notation(notation), Ok(state));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let rules = RuleSet {
+            play_into_won_boards: true,
+            ties_count_for_all: true,
+            centre_first_move: true,
+        };
+        for (players, rules) in [
+            (&b"XO"[..], RuleSet::STANDARD),
+            (b"XOA", rules),
+            (b"1234", rules),
+            (&[0xE9, 0xFF], RuleSet::STANDARD),
+        ] {
+            let mut state = GameState::new(players, rules);
+            loop {
+                let notation = state.to_notation().unwrap();
+                assert_eq!(GameState::from_notation(&notation), Ok(state));
+                match random_move(state) {
+                    Some(mov) => state = state.apply_move(mov),
+                    None => break,
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reserved_symbols() {
+        // A game can be set up with any symbols, but these would not read back as the same
+        // state, so they are refused both ways
+        for symbol in [b'-', b'/', b'=', b' ', b'\t', b'\n', 0x85, 0xA0] {
+            let mut state = GameState::new(&[b'X', symbol], RuleSet::STANDARD);
+            for _ in 0..4 {
+                assert!(matches!(
+                    state.to_notation(),
+                    Err(NotationError::BadPlayers(_))
+                ));
+                state = state.apply_move(random_move(state).unwrap());
+            }
+
+            // Written out by hand instead. Whitespace just splits the field, so it can't be caught.
+            if !char::from(symbol).is_whitespace() {
+                let boards = ["---------"; 9].join("/");
+                let notation = format!("{} X{} X -", boards, char::from(symbol));
+                assert!(GameState::from_notation(&notation).is_err());
+            }
+        }
+
+        // Other punctuation is fine
+        let mut state = GameState::new(b"+*", RuleSet::STANDARD);
+        while let Some(mov) = random_move(state) {
+            state = state.apply_move(mov);
+            let notation = state.to_notation().unwrap();
+            assert_eq!(GameState::from_notation(&notation), Ok(state));
+        }
+    }
+
+    #[test]
+    fn test_rules_field() {
+        let rules = RuleSet {
+            play_into_won_boards: true,
+            ..RuleSet::STANDARD
+        };
+        let boards = "X=XXXOOO---/".to_string() + &"---------/".repeat(7) + "---------";
+        let notation = format!("{} XO X A1 play-into-won", boards);
+        let state = GameState::from_notation(&notation).unwrap();
+        assert_eq!(state.rules(), rules);
+        assert_eq!(state.board_winner(0), Some(b'X'));
+        assert_eq!(state.to_notation(), Ok(notation));
+
+        assert_eq!(
+            GameState::from_notation(&format!("{} XO X A1 nonsense", boards)),
+            Err(NotationError::UnknownRules("nonsense".into()))
+        );
+        // Two lines on the board, so its winner must be given
+        assert_eq!(
+            GameState::from_notation(&format!("{} XO X A1 play-into-won", &boards[2..])),
+            Err(NotationError::BadWinner(0))
+        );
+        assert_eq!(
+            GameState::from_notation(&format!("A={} XO X A1 play-into-won", &boards[2..])),
+            Err(NotationError::BadWinner(0))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let empty = "---------/".repeat(8) + "---------";
+        let parse = |s: &str| GameState::from_notation(s);
+
+        assert_eq!(parse("XO X -"), Err(NotationError::WrongFieldCount(3)));
+        assert_eq!(
+            parse(&format!("{} XX X -", empty)),
+            Err(NotationError::BadPlayers("XX".into()))
+        );
+        assert_eq!(
+            parse(&format!("{}- XO X -", empty)),
+            Err(NotationError::BadBoards)
+        );
+        assert_eq!(
+            parse(&format!("{} XO A -", empty)),
+            Err(NotationError::BadNextToPlay("A".into()))
+        );
+        assert_eq!(
+            parse(&format!("{} XO O -", empty)),
+            Err(NotationError::PieceCounts)
+        );
+        assert_eq!(
+            parse(&format!("{} XO X D4", empty)),
+            Err(NotationError::BadSentTo("D4".into()))
+        );
+
+        let boards = "XXX------/".repeat(8) + "A--------";
+        assert_eq!(
+            parse(&format!("{} XO X -", boards)),
+            Err(NotationError::UnknownSymbol {
+                board: 8,
+                square: 0
+            })
+        );
+
+        // X has two pieces and O none, so it can't be X's turn
+        let boards = "XX-------/".to_string() + &"---------/".repeat(7) + "---------";
+        assert_eq!(
+            parse(&format!("{} XO X -", boards)),
+            Err(NotationError::PieceCounts)
+        );
+
+        let boards = "XXXOO----/".to_string() + &"---------/".repeat(7) + "O--------";
+        assert!(parse(&format!("{} XO X B1", boards)).is_ok());
+        assert_eq!(
+            parse(&format!("{} XO X A1", boards)),
+            Err(NotationError::SentToClosedBoard(0))
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Counting the leaves of the game tree to a fixed depth ("perft", after the chess testing
+//! routine). The counts for the first few plies are fixed by the rules, so any change to move
+//! generation shows up as a different count.
+//!
+//! As in chess, only positions exactly `depth` plies in are counted. A game which ends sooner
+//! adds nothing.
+
+use crate::{bitboard::BitGameState, GameState, Move, MoveList};
+
+/// A state perft can walk, so that one implementation serves both representations
+pub trait PerftState: Sized {
+    /// The legal moves from this state, empty once the game is over
+    fn legal_moves(&self) -> MoveList;
+    /// The state after a legal move
+    fn apply_move(&self, mov: Move) -> Self;
+}
+
+impl PerftState for GameState {
+    fn legal_moves(&self) -> MoveList {
+        GameState::legal_moves(self)
+    }
+
+    fn apply_move(&self, mov: Move) -> Self {
+        GameState::apply_move(self, mov)
+    }
+}
+
+impl PerftState for BitGameState {
+    fn legal_moves(&self) -> MoveList {
+        BitGameState::legal_moves(self)
+    }
+
+    fn apply_move(&self, mov: Move) -> Self {
+        BitGameState::apply_move(self, mov)
+    }
+}
+
+/// Number of positions reached by playing exactly `depth` plies from this state.
+/// Depth 0 counts just this state, and a finished game has nothing below it.
+pub fn perft<S: PerftState>(state: &S, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = state.legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves
+        .iter()
+        .map(|&mov| perft(&state.apply_move(mov), depth - 1))
+        .sum()
+}
+
+/// The `perft` count below each legal move from this state, in the order of `legal_moves`.
+/// The counts add up to `perft(state, depth)`. At depth 0 no moves are made, so the list is
+/// empty, as it is once the game is over.
+pub fn perft_divide<S: PerftState>(state: &S, depth: u32) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    state
+        .legal_moves()
+        .iter()
+        .map(|&mov| (mov, perft(&state.apply_move(mov), depth - 1)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RuleSet;
+
+    /// Counts from the empty board under the standard rules, for depths 0 to 5
+    const STANDARD_COUNTS: [u64; 6] = [1, 81, 720, 6336, 55080, 473256];
+
+    #[test]
+    fn test_standard_counts() {
+        let state = GameState::new(b"XO", RuleSet::STANDARD);
+        for (depth, &count) in STANDARD_COUNTS.iter().enumerate().take(5) {
+            assert_eq!(perft(&state, depth as u32), count);
+        }
+        for (depth, &count) in STANDARD_COUNTS.iter().enumerate() {
+            assert_eq!(perft(&BitGameState::from(&state), depth as u32), count);
+        }
+    }
+
+    #[test]
+    fn test_variant_counts() {
+        // The first move is one of the nine centre squares. The rest is as in the standard game.
+        let rules = RuleSet {
+            centre_first_move: true,
+            ..RuleSet::STANDARD
+        };
+        let state = BitGameState::new(b"XO", rules);
+        assert_eq!(
+            (0..5).map(|depth| perft(&state, depth)).collect::<Vec<_>>(),
+            [1, 9, 80, 704, 6120]
+        );
+
+        // No sub-board can be won within four plies, so playing into won boards changes nothing yet
+        let rules = RuleSet {
+            play_into_won_boards: true,
+            ties_count_for_all: true,
+            ..RuleSet::STANDARD
+        };
+        let state = BitGameState::new(b"XO", rules);
+        for (depth, &count) in STANDARD_COUNTS.iter().enumerate().take(5) {
+            assert_eq!(perft(&state, depth as u32), count);
+        }
+
+        // With three players nobody can win a board before ply 7
+        let state = BitGameState::new(b"XOA", RuleSet::STANDARD);
+        for (depth, &count) in STANDARD_COUNTS.iter().enumerate().take(5) {
+            assert_eq!(perft(&state, depth as u32), count);
+        }
+    }
+
+    #[test]
+    fn test_divide() {
+        let state = BitGameState::new(b"XO", RuleSet::STANDARD);
+        let divide = perft_divide(&state, 2);
+        assert_eq!(divide.len(), 81);
+        assert_eq!(divide.iter().map(|&(_, count)| count).sum::<u64>(), 720);
+
+        // Playing a sub-board's own square sends the opponent back to it, with one square gone
+        for &(mov, count) in &divide {
+            let expected = if mov.superboard == Some(mov.board) {
+                8
+            } else {
+                9
+            };
+            assert_eq!(count, expected);
+        }
+
+        assert_eq!(perft_divide(&state, 0), []);
+        let total: u64 = perft_divide(&state, 1)
+            .iter()
+            .map(|&(_, count)| count)
+            .sum();
+        assert_eq!(total, perft(&state, 1));
+    }
+
+    #[test]
+    fn test_finished_game() {
+        // A finished game is a position at depth 0, but has none below it
+        let over = GameState::new(b"XO", RuleSet::STANDARD)
+            .with_superboard([crate::board_shorthand(*b"XXX------"); 9]);
+        assert_eq!(perft(&over, 0), 1);
+        for depth in 1..4 {
+            assert_eq!(perft(&over, depth), 0);
+            assert_eq!(perft(&BitGameState::from(&over), depth), 0);
+        }
+    }
+}
@@ -0,0 +1,414 @@
+//! A PGN-style text format for whole games.
+//!
+//! ```text
+//! [Symbols "XO"]
+//! [X "Alice"]
+//! [O "Bob"]
+//! [Variant "Standard"]
+//! [Date "2021-11-27"]
+//! [Result "X"]
+//!
+//! 1. B2>B2 B1
+//! 2. C3 A3
+//! ```
+//!
+//! Each numbered line holds one round, with one move per player in `fmt_move` notation.
+//! `Result` is the winner's symbol, `Draw`, or `*` for an unfinished game.
+//!
+//! Symbols are written as the char with the same value, so `0xE9` is `é`. `*` and whitespace
+//! can't be symbols, since they would be confused with an unfinished result or break the tags.
+
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    fmt_move, parse_move, Game, GameOutcome, GameState, MoveError, ParseError, Player, RuleSet,
+    MAX_PLAYERS,
+};
+
+/// A recorded game along with its header tags
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Player names, in the same order as the game's players
+    pub names: Vec<String>,
+    /// The date the game was played on, as YYYY-MM-DD
+    pub date: Option<String>,
+    /// The game itself. Player symbols, rules and the result are taken from here.
+    pub game: Game,
+}
+
+/// Problems found while reading a record
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    /// A header tag on this line could not be understood
+    BadTag { line: usize },
+    /// A required header tag was missing
+    MissingTag(&'static str),
+    /// The `Symbols` tag was empty, too long, repeated a symbol, or used `*` or whitespace
+    BadSymbols(String),
+    /// The `Variant` tag did not name a known `RuleSet`
+    UnknownVariant(String),
+    /// A move could not be parsed
+    BadMove {
+        line: usize,
+        ply: usize,
+        error: ParseError,
+    },
+    /// A move was not legal in the position it was played from
+    IllegalMove {
+        line: usize,
+        ply: usize,
+        error: MoveError,
+    },
+    /// The `Result` tag disagrees with the outcome of the replayed moves
+    WrongResult { tag: String, actual: String },
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordError::BadTag { line } => write!(f, "line {}: malformed tag", line),
+            RecordError::MissingTag(tag) => write!(f, "missing [{}] tag", tag),
+            RecordError::BadSymbols(symbols) => write!(f, "bad player symbols \"{}\"", symbols),
+            RecordError::UnknownVariant(variant) => write!(f, "unknown variant \"{}\"", variant),
+            RecordError::BadMove { line, ply, error } => {
+                write!(f, "line {}, ply {}: {}", line, ply, error)
+            }
+            RecordError::IllegalMove { line, ply, error } => {
+                write!(f, "line {}, ply {}: illegal move: {}", line, ply, error)
+            }
+            RecordError::WrongResult { tag, actual } => write!(
+                f,
+                "result tag says \"{}\" but the moves give \"{}\"",
+                tag, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl Record {
+    /// Record a game, naming each player by their symbol and dating it today
+    pub fn new(game: Game) -> Self {
+        Self {
+            names: game
+                .state()
+                .players()
+                .iter()
+                .map(|&p| char::from(p).to_string())
+                .collect(),
+            date: Some(today()),
+            game,
+        }
+    }
+}
+
+/// The text used for an outcome in the `Result` tag
+pub fn result_tag(outcome: GameOutcome) -> String {
+    match outcome {
+        GameOutcome::Won(winner) => char::from(winner).to_string(),
+        GameOutcome::Draw => "Draw".into(),
+        GameOutcome::Ongoing => "*".into(),
+    }
+}
+
+/// Write a record in the text format. Fails if a player's symbol can't be written; see the module docs.
+pub fn write_record(record: &Record) -> Result<String, RecordError> {
+    let state = record.game.initial_state();
+    let symbols: String = state.players().iter().map(|&p| char::from(p)).collect();
+    if !valid_symbols(state.players()) {
+        return Err(RecordError::BadSymbols(symbols));
+    }
+
+    let mut s = String::new();
+    let mut tag = |name: &str, value: &str| writeln!(s, "[{} \"{}\"]", name, escape(value));
+    tag("Symbols", &symbols).unwrap();
+    for (symbol, name) in symbols.chars().zip(&record.names) {
+        tag(&symbol.to_string(), name).unwrap();
+    }
+    tag("Variant", &state.rules().to_string()).unwrap();
+    if let Some(date) = &record.date {
+        tag("Date", date).unwrap();
+    }
+    tag("Result", &result_tag(record.game.outcome())).unwrap();
+    s.push('\n');
+
+    for (round, moves) in record
+        .game
+        .history()
+        .chunks(state.num_players())
+        .enumerate()
+    {
+        write!(s, "{}.", round + 1).unwrap();
+        for &mov in moves {
+            write!(s, " {}", fmt_move(mov)).unwrap();
+        }
+        s.push('\n');
+    }
+
+    Ok(s)
+}
+
+/// Read a record from the text format, replaying and validating every move
+pub fn read_record(text: &str) -> Result<Record, RecordError> {
+    let mut tags: Vec<(String, String)> = vec![];
+    let mut game: Option<Game> = None;
+    let mut ply = 0;
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            if game.is_some() {
+                return Err(RecordError::BadTag { line: line_no });
+            }
+            tags.push(parse_tag(line).ok_or(RecordError::BadTag { line: line_no })?);
+            continue;
+        }
+
+        if game.is_none() {
+            game = Some(Game::new(GameState::new(&symbols(&tags)?, rules(&tags)?)));
+        }
+        let game = game.as_mut().unwrap();
+
+        for token in line.split_whitespace() {
+            if token.strip_suffix('.').is_some_and(is_number) {
+                continue;
+            }
+
+            ply += 1;
+            let mov = parse_move(token).map_err(|error| RecordError::BadMove {
+                line: line_no,
+                ply,
+                error,
+            })?;
+            game.play(mov).map_err(|error| RecordError::IllegalMove {
+                line: line_no,
+                ply,
+                error,
+            })?;
+        }
+    }
+
+    let symbols = symbols(&tags)?;
+    let game = match game {
+        Some(game) => game,
+        None => Game::new(GameState::new(&symbols, rules(&tags)?)),
+    };
+    let get = |name: &str| {
+        tags.iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.clone())
+    };
+
+    if let Some(tag) = get("Result") {
+        let actual = result_tag(game.outcome());
+        if tag != actual {
+            return Err(RecordError::WrongResult { tag, actual });
+        }
+    }
+
+    Ok(Record {
+        names: symbols
+            .iter()
+            .map(|&p| {
+                let symbol = char::from(p).to_string();
+                get(&symbol).unwrap_or(symbol)
+            })
+            .collect(),
+        date: get("Date"),
+        game,
+    })
+}
+
+fn symbols(tags: &[(String, String)]) -> Result<Vec<Player>, RecordError> {
+    let (_, symbols) = tags
+        .iter()
+        .find(|(tag, _)| tag == "Symbols")
+        .ok_or(RecordError::MissingTag("Symbols"))?;
+    let bytes: Option<Vec<Player>> = symbols.chars().map(|c| Player::try_from(c).ok()).collect();
+    match bytes {
+        Some(bytes) if valid_symbols(&bytes) => Ok(bytes),
+        _ => Err(RecordError::BadSymbols(symbols.clone())),
+    }
+}
+
+/// Symbols must be non-empty, not too many, unique, and not `*` or whitespace
+fn valid_symbols(symbols: &[Player]) -> bool {
+    let unique = symbols
+        .iter()
+        .enumerate()
+        .all(|(i, p)| !symbols[..i].contains(p));
+    let allowed = |&p: &Player| p != b'*' && !char::from(p).is_whitespace();
+    !symbols.is_empty() && symbols.len() <= MAX_PLAYERS && unique && symbols.iter().all(allowed)
+}
+
+fn rules(tags: &[(String, String)]) -> Result<RuleSet, RecordError> {
+    match tags.iter().find(|(tag, _)| tag == "Variant") {
+        Some((_, variant)) => variant
+            .parse()
+            .map_err(|_| RecordError::UnknownVariant(variant.clone())),
+        None => Ok(RuleSet::STANDARD),
+    }
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Parse `[Name "value"]`
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_string(), unescape(value)?))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push(chars.next()?),
+            '"' => return None,
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+/// Today's date (UTC) as YYYY-MM-DD
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (y, m, d) = civil_from_days((secs / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date.
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::random_move;
+
+    #[test]
+    fn test_round_trip() {
+        let ties = RuleSet {
+            ties_count_for_all: true,
+            ..RuleSet::STANDARD
+        };
+        for (players, rules) in [
+            (&b"XO"[..], RuleSet::STANDARD),
+            (b"XOA", ties),
+            (&[0xE9, 0xFF], RuleSet::STANDARD),
+        ] {
+            let mut game = Game::new(GameState::new(players, rules));
+            while let Some(mov) = random_move(*game.state()) {
+                game.play(mov).unwrap();
+            }
+
+            let mut record = Record::new(game);
+            record.names[0] = "Alice \"the\" \\first\\".into();
+            let text = write_record(&record).unwrap();
+            assert_eq!(read_record(&text), Ok(record));
+        }
+    }
+
+    #[test]
+    fn test_unwritable_symbols() {
+        for players in [&b"X*"[..], b"X O"] {
+            let record = Record::new(Game::new(GameState::new(players, RuleSet::STANDARD)));
+            let symbols: String = players.iter().map(|&p| char::from(p)).collect();
+            assert_eq!(write_record(&record), Err(RecordError::BadSymbols(symbols)));
+        }
+        assert_eq!(
+            read_record("[Symbols \"X*\"]\n"),
+            Err(RecordError::BadSymbols("X*".into()))
+        );
+    }
+
+    #[test]
+    fn test_illegal_move() {
+        let text = "[Symbols \"XO\"]\n\n1. B2>B2 B2\n2. A1 A1\n";
+        assert_eq!(
+            read_record(text),
+            Err(RecordError::IllegalMove {
+                line: 3,
+                ply: 2,
+                error: MoveError::Occupied {
+                    board: 4,
+                    square: 4
+                }
+            })
+        );
+
+        let text = "[Symbols \"XO\"]\n1. B2>B2 B1\n2. A1 D4\n";
+        assert_eq!(
+            read_record(text),
+            Err(RecordError::BadMove {
+                line: 3,
+                ply: 4,
+                error: ParseError::InvalidCoord("D4".into())
+            })
+        );
+    }
+
+    #[test]
+    fn test_header_errors() {
+        assert_eq!(
+            read_record("1. B2>B2\n"),
+            Err(RecordError::MissingTag("Symbols"))
+        );
+        assert_eq!(
+            read_record("[Symbols \"XX\"]\n"),
+            Err(RecordError::BadSymbols("XX".into()))
+        );
+        assert_eq!(
+            read_record("[Symbols \"XO\"]\n[Variant \"Freestyle\"]\n"),
+            Err(RecordError::UnknownVariant("Freestyle".into()))
+        );
+        assert_eq!(
+            read_record("[Symbols XO]\n"),
+            Err(RecordError::BadTag { line: 1 })
+        );
+        assert_eq!(
+            read_record("[Symbols \"XO\"]\n[Result \"X\"]\n1. B2>B2\n"),
+            Err(RecordError::WrongResult {
+                tag: "X".into(),
+                actual: "*".into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(18958), (2021, 11, 27));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+    }
+}
@@ -0,0 +1,185 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{Player, LINES};
+
+/// Variations on the rules. The default is the house rules described in RULES.md.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuleSet {
+    /// Sub-boards stay open after they are won, as long as they have empty squares.
+    /// Players sent to such a board must play there instead of picking freely.
+    pub play_into_won_boards: bool,
+    /// Tied sub-boards count towards a line for every player, instead of as blanks
+    pub ties_count_for_all: bool,
+    /// The first move of the game must be made in the centre sub-board
+    pub centre_first_move: bool,
+}
+
+/// How a sub-board counts towards lines on the superboard
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BoardResult {
+    /// Not won, and still has empty squares
+    Open,
+    /// Won by the given player
+    Won(Player),
+    /// Full, and not won by anyone
+    Tied,
+}
+
+/// Names used for each rule in `RuleSet`'s text form
+const RULE_NAMES: [&str; 3] = ["play-into-won", "ties-count", "centre-first"];
+
+impl RuleSet {
+    /// The house rules from RULES.md
+    pub const STANDARD: Self = Self {
+        play_into_won_boards: false,
+        ties_count_for_all: false,
+        centre_first_move: false,
+    };
+
+    fn flags(&self) -> [bool; 3] {
+        [
+            self.play_into_won_boards,
+            self.ties_count_for_all,
+            self.centre_first_move,
+        ]
+    }
+
+    fn flags_mut(&mut self) -> [&mut bool; 3] {
+        [
+            &mut self.play_into_won_boards,
+            &mut self.ties_count_for_all,
+            &mut self.centre_first_move,
+        ]
+    }
+
+    /// Returns true if a sub-board with this result counts towards a line for `player`
+    pub fn counts_for(&self, result: BoardResult, player: Player) -> bool {
+        match result {
+            BoardResult::Won(winner) => winner == player,
+            BoardResult::Tied => self.ties_count_for_all,
+            BoardResult::Open => false,
+        }
+    }
+
+    /// Returns true if `player` has completed a line on a superboard with these sub-board results.
+    /// More than one player can only have a line when `ties_count_for_all` is set.
+    pub fn has_line(&self, results: &[BoardResult; 9], player: Player) -> bool {
+        LINES.iter().any(|line| {
+            line.iter()
+                .all(|&idx| self.counts_for(results[idx], player))
+        })
+    }
+}
+
+impl Display for RuleSet {
+    /// `Standard`, or the names of the variant rules joined by `+`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = RULE_NAMES
+            .iter()
+            .zip(self.flags())
+            .filter_map(|(&name, on)| on.then_some(name))
+            .collect();
+
+        if names.is_empty() {
+            write!(f, "Standard")
+        } else {
+            write!(f, "{}", names.join("+"))
+        }
+    }
+}
+
+/// A rule name in a variant string was not recognised
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UnknownRule(pub String);
+
+impl Display for UnknownRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown rule \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for UnknownRule {}
+
+impl FromStr for RuleSet {
+    type Err = UnknownRule;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = RuleSet::STANDARD;
+        for name in s.split('+') {
+            if name.eq_ignore_ascii_case("standard") {
+                continue;
+            }
+            let idx = RULE_NAMES
+                .iter()
+                .position(|&n| n.eq_ignore_ascii_case(name))
+                .ok_or_else(|| UnknownRule(name.to_string()))?;
+            *rules.flags_mut()[idx] = true;
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(RuleSet::default().to_string(), "Standard");
+        for bits in 0..8 {
+            let mut rules = RuleSet::default();
+            for (i, flag) in rules.flags_mut().into_iter().enumerate() {
+                *flag = bits & (1 << i) != 0;
+            }
+            assert_eq!(rules.to_string().parse(), Ok(rules));
+        }
+        assert_eq!(
+            "ties-count+centre-first".parse(),
+            Ok(RuleSet {
+                ties_count_for_all: true,
+                centre_first_move: true,
+                ..RuleSet::STANDARD
+            })
+        );
+        assert_eq!(
+            "ties-count+nonsense".parse::<RuleSet>(),
+            Err(UnknownRule("nonsense".into()))
+        );
+    }
+
+    #[test]
+    fn test_has_line() {
+        use BoardResult::*;
+        let results = [
+            Won(b'X'),
+            Tied,
+            Tied,
+            Tied,
+            Won(b'O'),
+            Open,
+            Tied,
+            Open,
+            Open,
+        ];
+
+        for player in [b'X', b'O', b'A'] {
+            assert!(!RuleSet::STANDARD.has_line(&results, player));
+        }
+
+        let ties = RuleSet {
+            ties_count_for_all: true,
+            ..RuleSet::STANDARD
+        };
+        // Top row for X and anti-diagonal for O. No line is all ties, so nobody else has one.
+        assert!(ties.has_line(&results, b'X'));
+        assert!(ties.has_line(&results, b'O'));
+        assert!(!ties.has_line(&results, b'A'));
+
+        // A line of ties counts for everyone
+        let mut all_tied = results;
+        all_tied[5] = Tied;
+        all_tied[8] = Tied;
+        assert!(ties.has_line(&all_tied, b'A'));
+    }
+}
@@ -0,0 +1,116 @@
+//! Serialize a `SuperBoard` as nine strings of nine squares, laid out the way it is printed.
+//! Use with `#[serde(with = "crate::serde_grid")]`. `GameState` is serialized through
+//! `GameStateRepr`, which writes its superboard this way and its players as one string, e.g. `"XO"`.
+//!
+//! ```text
+//! [
+//!   "X--------",
+//!   "---------",
+//!   "---------",
+//!   "----O----",
+//!   ...
+//! ]
+//! ```
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    notation::{to_symbols, NotationError},
+    GameState, Player, RuleSet, SuperBoard, EMPTY_SUPERBOARD,
+};
+
+/// The serialized form of a `GameState`, which leaves out the Zobrist key
+/// and works it out again on the way back in, after checking the rest is consistent
+#[derive(Serialize, Deserialize)]
+pub struct GameStateRepr {
+    /// The board state
+    #[serde(with = "self")]
+    superboard: SuperBoard,
+    /// The winner of each sub-board, if any
+    board_winners: [Option<Player>; 9],
+    /// The players' symbols in one string, ordered by who goes first
+    players: String,
+    /// Index into `players` of the player to move
+    next_to_play: usize,
+    /// If any, the index of the superboard square the player has been sent to
+    sent_to: Option<usize>,
+    /// The rules this game is played under
+    rules: RuleSet,
+}
+
+impl From<GameState> for GameStateRepr {
+    fn from(state: GameState) -> Self {
+        Self {
+            superboard: *state.superboard(),
+            board_winners: std::array::from_fn(|board| state.board_winner(board)),
+            players: state.players().iter().map(|&p| char::from(p)).collect(),
+            next_to_play: state.next_to_play_idx(),
+            sent_to: state.sent_to(),
+            rules: state.rules(),
+        }
+    }
+}
+
+impl TryFrom<GameStateRepr> for GameState {
+    type Error = NotationError;
+
+    /// Runs the same checks as `GameState::from_notation`
+    fn try_from(repr: GameStateRepr) -> Result<Self, Self::Error> {
+        let players = to_symbols(&repr.players).ok_or(NotationError::BadPlayers(repr.players))?;
+        GameState::from_checked_parts(
+            repr.superboard,
+            repr.board_winners,
+            &players,
+            repr.next_to_play,
+            repr.sent_to,
+            repr.rules,
+        )
+    }
+}
+
+/// The (sub-board, square) shown at row `y`, column `x` of the 9x9 grid
+fn grid_index(y: usize, x: usize) -> (usize, usize) {
+    ((y / 3) * 3 + x / 3, (y % 3) * 3 + x % 3)
+}
+
+pub fn serialize<S: Serializer>(superboard: &SuperBoard, serializer: S) -> Result<S::Ok, S::Error> {
+    let rows: Vec<String> = (0..9)
+        .map(|y| {
+            (0..9)
+                .map(|x| {
+                    let (board, square) = grid_index(y, x);
+                    char::from(superboard[board][square].unwrap_or(b'-'))
+                })
+                .collect()
+        })
+        .collect();
+    rows.serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SuperBoard, D::Error> {
+    let rows = Vec::<String>::deserialize(deserializer)?;
+    if rows.len() != 9 {
+        return Err(D::Error::invalid_length(rows.len(), &"9 rows"));
+    }
+
+    let mut superboard = EMPTY_SUPERBOARD;
+    for (y, row) in rows.iter().enumerate() {
+        // Symbols are written as the char with the same value, so read them back the same way
+        let squares: Option<Vec<u8>> = row.chars().map(|c| u8::try_from(c).ok()).collect();
+        let squares = match squares {
+            Some(squares) if squares.len() == 9 => squares,
+            _ => {
+                return Err(D::Error::custom(format!(
+                    "row {} should have 9 squares, found \"{}\"",
+                    y, row
+                )))
+            }
+        };
+        for (x, c) in squares.into_iter().enumerate() {
+            let (board, square) = grid_index(y, x);
+            superboard[board][square] = (c != b'-').then_some(c);
+        }
+    }
+
+    Ok(superboard)
+}
@@ -3,16 +3,23 @@ use std::collections::HashMap;
 type StateMap = HashMap<SingleBoardState, Scores>;
 
 use crate::{
-    is_board_won, open_board_squares, print_superboard, Board, GamePrintGuides, Player,
+    open_board_squares, print_superboard, scan_board_won, Board, GamePrintGuides, Player,
     EMPTY_BOARD, EMPTY_SUPERBOARD,
 };
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SingleBoardState {
     pub board: Board,
     pub x_is_next: bool,
 }
 
+impl Default for SingleBoardState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SingleBoardState {
     pub fn new() -> Self {
         Self {
@@ -35,12 +42,17 @@ impl SingleBoardState {
         Self { board, x_is_next }
     }
 
+    // These scan the board rather than using `board_table`, which is built from them
+
     pub fn successors(self) -> impl Iterator<Item = Self> {
-        open_board_squares(self.board).map(move |mov| self.apply(mov))
+        let is_won = self.winner().is_some();
+        (0..9)
+            .filter(move |&mov| !is_won && self.board[mov].is_none())
+            .map(move |mov| self.apply(mov))
     }
 
     pub fn winner(&self) -> Option<Player> {
-        is_board_won(&self.board)
+        scan_board_won(&self.board)
     }
 }
 
@@ -62,18 +74,20 @@ fn add_scores((a, b): Scores, (c, d): Scores) -> Scores {
     (a + c, b + d)
 }
 
+/// Look up the scores of a state, which may be any of the symmetries of the one stored
+pub fn scores(table: &StateMap, state: SingleBoardState) -> Option<Scores> {
+    table.get(&canonical(state)).copied()
+}
+
+/// Scores of a state, which must be canonical
 fn recursive_step(state: SingleBoardState, table: &mut StateMap) -> Scores {
     let score = match state.winner() {
         Some(b'X') => (1, 0),
         Some(b'O') => (0, 1),
         _ => {
             let mut cumulative_score = (0, 0);
-            for succ in state.successors() {
-                let maybe_board = invariant_states(succ)
-                    .iter()
-                    .filter_map(|s| table.get(s))
-                    .next();
-                let score = match maybe_board {
+            for succ in state.successors().map(canonical) {
+                let score = match table.get(&succ) {
                     Some(&s) => s,
                     None => recursive_step(succ, table),
                 };
@@ -86,6 +100,128 @@ fn recursive_step(state: SingleBoardState, table: &mut StateMap) -> Scores {
     score
 }
 
+/// The result of a position with perfect play, for the player to move
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// The value of a position with perfect play, and how many plies it takes to get there.
+/// The winner wins as quickly as they can, and the loser holds out as long as they can.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Solution {
+    pub value: Value,
+    pub distance: u32,
+}
+
+impl Solution {
+    /// The solution for the player who moves into a position with this solution
+    fn for_mover(self) -> Self {
+        Self {
+            value: match self.value {
+                Value::Win => Value::Loss,
+                Value::Draw => Value::Draw,
+                Value::Loss => Value::Win,
+            },
+            distance: self.distance + 1,
+        }
+    }
+
+    /// Higher is better for the player to move
+    fn rank(self) -> i32 {
+        let distance = self.distance as i32;
+        match self.value {
+            Value::Win => 100 - distance,
+            Value::Draw => 0,
+            Value::Loss => distance - 100,
+        }
+    }
+}
+
+type SolutionMap = HashMap<SingleBoardState, Solution>;
+
+/// Solve every position reachable from the empty board. Only canonical positions are stored,
+/// so look positions up with `solution`.
+pub fn solve() -> SolutionMap {
+    let mut table = SolutionMap::new();
+    solve_step(SingleBoardState::new(), &mut table);
+    table
+}
+
+/// Solve any position, not just those reachable from the empty board, adding it to the table
+pub fn solve_from(table: &mut SolutionMap, state: SingleBoardState) -> Solution {
+    let state = canonical(state);
+    match table.get(&state) {
+        Some(&solution) => solution,
+        None => solve_step(state, table),
+    }
+}
+
+/// Solution of a state, which must be canonical
+fn solve_step(state: SingleBoardState, table: &mut SolutionMap) -> Solution {
+    let to_move = if state.x_is_next { b'X' } else { b'O' };
+    let solution = if let Some(winner) = state.winner() {
+        // Usually the last player to move, but boards set up by hand may have either winner
+        Solution {
+            value: if winner == to_move {
+                Value::Win
+            } else {
+                Value::Loss
+            },
+            distance: 0,
+        }
+    } else {
+        state
+            .successors()
+            .map(canonical)
+            .map(|succ| {
+                let solution = match table.get(&succ) {
+                    Some(&s) => s,
+                    None => solve_step(succ, table),
+                };
+                solution.for_mover()
+            })
+            .max_by_key(|s| s.rank())
+            .unwrap_or(Solution {
+                value: Value::Draw,
+                distance: 0,
+            })
+    };
+    table.insert(state, solution);
+    solution
+}
+
+/// Look up the solution of a position, which may be any of the symmetries of the one stored
+pub fn solution(table: &SolutionMap, state: SingleBoardState) -> Option<Solution> {
+    table.get(&canonical(state)).copied()
+}
+
+/// The squares the player to move can play to get the best result, quickest
+pub fn optimal_moves(table: &SolutionMap, state: SingleBoardState) -> Vec<usize> {
+    let Some(best) = solution(table, state) else {
+        return vec![];
+    };
+    if state.winner().is_some() {
+        return vec![];
+    }
+    open_board_squares(state.board)
+        .filter(|&mov| solution(table, state.apply(mov)).map(Solution::for_mover) == Some(best))
+        .collect()
+}
+
+/// The representative of a state's symmetries: the one with the smallest board.
+/// Tables in this module store only canonical states.
+pub fn canonical(state: SingleBoardState) -> SingleBoardState {
+    invariant_states(state)
+        .into_iter()
+        .min_by_key(|s| s.board)
+        .unwrap()
+}
+
 pub fn invariant_states(state: SingleBoardState) -> [SingleBoardState; 8] {
     invariant_boards(state.board).map(|board| SingleBoardState::from_board(board, state.x_is_next))
 }
@@ -151,6 +287,8 @@ pub fn invariant_boards(board: Board) -> [Board; 8] {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::board_shorthand;
+    use std::collections::HashSet;
 
     #[test]
     fn test_combinatorics() {
@@ -169,4 +307,112 @@ mod tests {
         let o_wins = tree.keys().filter(|s| s.winner() == Some(b'O')).count();
         assert_eq!(o_wins, 44);
     }
+
+    #[test]
+    fn test_canonical() {
+        let tree = game_tree();
+        assert!(tree.keys().all(|&state| canonical(state) == state));
+
+        let solutions = solve();
+        assert_eq!(solutions.len(), tree.len());
+
+        // Every symmetry of every reachable state is found, with the same scores
+        let mut queue = vec![SingleBoardState::new()];
+        let mut seen = HashSet::new();
+        while let Some(state) = queue.pop() {
+            if !seen.insert(state) {
+                continue;
+            }
+            let expected = (
+                scores(&tree, state).unwrap(),
+                solution(&solutions, state).unwrap(),
+            );
+            for symmetric in invariant_states(state) {
+                assert_eq!(canonical(symmetric), canonical(state));
+                assert_eq!(
+                    (
+                        scores(&tree, symmetric).unwrap(),
+                        solution(&solutions, symmetric).unwrap()
+                    ),
+                    expected
+                );
+            }
+            queue.extend(state.successors());
+        }
+        assert_eq!(seen.len(), 5478);
+    }
+
+    #[test]
+    fn test_solve() {
+        let table = solve();
+        let solved = |board, x_is_next| {
+            let state = SingleBoardState::from_board(board_shorthand(board), x_is_next);
+            (
+                solution(&table, state).unwrap(),
+                optimal_moves(&table, state),
+            )
+        };
+
+        // Tic-tac-toe is a draw, and every opening move keeps it one
+        let (empty, moves) = solved(*b"---------", true);
+        assert_eq!(
+            empty,
+            Solution {
+                value: Value::Draw,
+                distance: 9
+            }
+        );
+        assert_eq!(moves, (0..9).collect::<Vec<_>>());
+
+        // O has to take the centre after a corner opening
+        let (_, moves) = solved(*b"X--------", false);
+        assert_eq!(moves, [4]);
+
+        // X wins at once, rather than by blocking O first
+        let (win, moves) = solved(*b"XX-OO----", true);
+        assert_eq!(
+            win,
+            Solution {
+                value: Value::Win,
+                distance: 1
+            }
+        );
+        assert_eq!(moves, [2]);
+
+        // Anywhere else loses to a fork, which O can only delay
+        let (fork, _) = solved(*b"X-------O", true);
+        assert_eq!(
+            fork,
+            Solution {
+                value: Value::Win,
+                distance: 5
+            }
+        );
+
+        // Finished games
+        let (won, moves) = solved(*b"XXXOO----", false);
+        assert_eq!(
+            won,
+            Solution {
+                value: Value::Loss,
+                distance: 0
+            }
+        );
+        assert!(moves.is_empty());
+        let (tied, _) = solved(*b"XOXXOOOXX", false);
+        assert_eq!(tied.value, Value::Draw);
+        assert_eq!(tied.distance, 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        for state in game_tree().into_keys() {
+            let json = serde_json::to_string(&state).unwrap();
+            assert_eq!(
+                serde_json::from_str::<SingleBoardState>(&json).unwrap(),
+                state
+            );
+        }
+    }
 }
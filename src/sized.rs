@@ -0,0 +1,608 @@
+//! Ultimate tic-tac-toe on an N×N grid of N×N boards, where `win_len` in a row wins a board
+//! (and `win_len` won boards in a row wins the game), under any `RuleSet`.
+//!
+//! Squares and sub-boards are numbered row-major from 0 to N×N - 1, just as in the 3×3 game,
+//! so `Move`, `MoveError`, `RuleSet` and `BoardResult` are shared with it. The 3×3 `GameState`
+//! and `BitGameState` remain the fast path for the usual game; `SizedGameState<3>` with a
+//! `win_len` of 3 plays the same under every rule set.
+//!
+//! The line, outcome and layout code here is written for any size, and `GameState` uses it too
+//! with N and `win_len` both 3, so the two can't drift apart.
+//!
+//! N can be at most `MAX_SIZE`, so that every coordinate is one letter and one digit.
+
+use crate::{
+    BoardResult, GameOutcome, GamePrintGuides, Move, MoveError, Player, RuleSet, Square,
+    MAX_PLAYERS,
+};
+
+/// The largest N supported, the most whose coordinates `sized_coord` can write as one letter
+/// and one digit
+pub const MAX_SIZE: usize = 9;
+
+/// Row-major N×N board
+pub type SizedBoard<const N: usize> = [[Square; N]; N];
+
+/// Row-major N×N board of boards
+pub type SizedSuperBoard<const N: usize> = [[SizedBoard<N>; N]; N];
+
+/// Directions a line can run in, as (row, column) steps
+const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+/// Every line of `win_len` cells on an `n`×`n` grid, each as an iterator over row-major indices
+pub fn lines(
+    n: usize,
+    win_len: usize,
+) -> impl Iterator<Item = impl Iterator<Item = usize> + Clone> {
+    let span = win_len - 1;
+    DIRECTIONS.into_iter().flat_map(move |(dr, dc)| {
+        // Only start where the line stays on the grid
+        let rows = 0..n - span * dr as usize;
+        let cols = match dc {
+            1 => 0..n - span,
+            0 => 0..n,
+            _ => span..n,
+        };
+        rows.flat_map(move |row| cols.clone().map(move |col| (row, col)))
+            .map(move |(row, col)| {
+                let start = (row * n + col) as isize;
+                let step = dr * n as isize + dc;
+                (0..win_len as isize).map(move |i| (start + step * i) as usize)
+            })
+    })
+}
+
+/// Returns the owner of the first complete line of `win_len` cells, where `cell` gives the owner of each index
+pub(crate) fn find_line(
+    n: usize,
+    win_len: usize,
+    cell: impl Fn(usize) -> Square,
+) -> Option<Player> {
+    lines(n, win_len).find_map(|mut line| {
+        let player = cell(line.next()?)?;
+        line.all(|idx| cell(idx) == Some(player)).then_some(player)
+    })
+}
+
+/// Returns true if `player` could still complete a line of `win_len` cells, where `cell` gives
+/// the owner of each index
+pub(crate) fn can_complete_line(
+    n: usize,
+    win_len: usize,
+    cell: impl Fn(usize) -> Square,
+    player: Player,
+) -> bool {
+    lines(n, win_len).any(|mut line| line.all(|idx| cell(idx).map_or(true, |p| p == player)))
+}
+
+/// Works out the outcome of a game on an `n`×`n` superboard from the result of each sub-board.
+/// `can_be_won_by` says whether a player could still win an open sub-board.
+/// A game is drawn early once no player can complete a line on the superboard.
+pub(crate) fn outcome(
+    n: usize,
+    win_len: usize,
+    players: &[Player],
+    rules: RuleSet,
+    result: impl Fn(usize) -> BoardResult,
+    can_be_won_by: impl Fn(usize, Player) -> bool,
+) -> GameOutcome {
+    let mut results = [BoardResult::Open; MAX_SIZE * MAX_SIZE];
+    for (idx, slot) in results[..n * n].iter_mut().enumerate() {
+        *slot = result(idx);
+    }
+
+    let has_line = |player: Player| {
+        lines(n, win_len).any(|mut line| line.all(|idx| rules.counts_for(results[idx], player)))
+    };
+
+    if let Some(&winner) = players.iter().find(|&&p| has_line(p)) {
+        return GameOutcome::Won(winner);
+    }
+
+    let can_win = |&player: &Player| {
+        lines(n, win_len).any(|mut line| {
+            line.all(|idx| match results[idx] {
+                BoardResult::Open => rules.ties_count_for_all || can_be_won_by(idx, player),
+                result => rules.counts_for(result, player),
+            })
+        })
+    };
+
+    if players.iter().any(can_win) {
+        GameOutcome::Ongoing
+    } else {
+        GameOutcome::Draw
+    }
+}
+
+/// Returns the element of a grid at a row-major index
+fn at<T: Copy, const N: usize>(grid: &[[T; N]; N], idx: usize) -> T {
+    grid[idx / N][idx % N]
+}
+
+/// Returns Some(winner) if this board has `win_len` in a row
+pub fn is_sized_board_won<const N: usize>(board: &SizedBoard<N>, win_len: usize) -> Option<Player> {
+    find_line(N, win_len, |idx| at(board, idx))
+}
+
+/// Returns true if the given player could still get `win_len` in a row on this board
+pub fn sized_board_can_be_won_by<const N: usize>(
+    board: &SizedBoard<N>,
+    win_len: usize,
+    player: Player,
+) -> bool {
+    can_complete_line(N, win_len, |idx| at(board, idx), player)
+}
+
+/// A single game state on N×N boards
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct SizedGameState<const N: usize> {
+    /// The board state
+    pub superboard: SizedSuperBoard<N>,
+    /// The winner of each sub-board, if any
+    pub board_winners: [[Option<Player>; N]; N],
+    /// Number in a row needed to win a board, and number of boards in a row to win the game
+    pub win_len: usize,
+    /// The players in this game, ordered by who goes first.
+    pub players: [Player; MAX_PLAYERS],
+    /// Index of the player from `players` who will make the next move
+    pub next_to_play: usize,
+    /// If any, the index of the superboard square the player has been sent to.
+    pub sent_to: Option<usize>,
+    /// Number of players
+    pub num_players: usize,
+    /// The rules this game is played under
+    pub rules: RuleSet,
+}
+
+impl<const N: usize> SizedGameState<N> {
+    /// The sub-board the first move must be made in under `RuleSet::centre_first_move`
+    const CENTRE: usize = N * N / 2;
+
+    /// Create a new game given player symbols, the number in a row needed to win, and rules.
+    /// `RuleSet::centre_first_move` needs an odd N, so that there is a centre sub-board.
+    pub fn new(players: &[Player], win_len: usize, rules: RuleSet) -> Self {
+        assert!(
+            (1..=MAX_SIZE).contains(&N),
+            "N ({}) must be between 1 and {}.",
+            N,
+            MAX_SIZE
+        );
+        assert!(
+            N % 2 == 1 || !rules.centre_first_move,
+            "centre_first_move needs an odd N, not {}.",
+            N
+        );
+        assert!(
+            (1..=N).contains(&win_len),
+            "win_len ({}) must be between 1 and {}.",
+            win_len,
+            N
+        );
+        assert!(
+            players.len() <= MAX_PLAYERS,
+            "MAX_PLAYERS ({}) exceeded.",
+            MAX_PLAYERS
+        );
+        let mut players_array = [b'*'; MAX_PLAYERS];
+        players_array[..players.len()].copy_from_slice(players);
+
+        Self {
+            superboard: [[[[None; N]; N]; N]; N],
+            board_winners: [[None; N]; N],
+            win_len,
+            players: players_array,
+            next_to_play: 0,
+            sent_to: None,
+            num_players: players.len(),
+            rules,
+        }
+    }
+
+    /// Returns the symbol of the next player to play
+    pub fn next_to_play(&self) -> Player {
+        self.players[self.next_to_play]
+    }
+
+    /// Returns the symbols of the players in this game, ordered by who goes first
+    pub fn players(&self) -> &[Player] {
+        &self.players[..self.num_players]
+    }
+
+    /// Returns the sub-board at the given row-major index
+    pub fn board(&self, board_idx: usize) -> &SizedBoard<N> {
+        &self.superboard[board_idx / N][board_idx % N]
+    }
+
+    /// Returns how the given sub-board counts towards lines on the superboard
+    pub fn board_result(&self, board_idx: usize) -> BoardResult {
+        match at(&self.board_winners, board_idx) {
+            Some(winner) => BoardResult::Won(winner),
+            None if self.board(board_idx).iter().flatten().all(Option::is_some) => {
+                BoardResult::Tied
+            }
+            None => BoardResult::Open,
+        }
+    }
+
+    /// Returns the squares which may be played on the given sub-board under this game's rules
+    pub fn open_squares(&self, board_idx: usize) -> impl Iterator<Item = usize> {
+        let closed =
+            at(&self.board_winners, board_idx).is_some() && !self.rules.play_into_won_boards;
+        let board = *self.board(board_idx);
+        (0..N * N).filter(move |&idx| !closed && at(&board, idx).is_none())
+    }
+
+    /// Returns whether the game has been won, drawn, or is still in progress.
+    /// A game is drawn early once no player can complete a line on the superboard.
+    pub fn outcome(&self) -> GameOutcome {
+        outcome(
+            N,
+            self.win_len,
+            self.players(),
+            self.rules,
+            |idx| self.board_result(idx),
+            |idx, player| sized_board_can_be_won_by(self.board(idx), self.win_len, player),
+        )
+    }
+
+    /// Returns the sub-boards the next player may play on
+    fn playable_boards(&self) -> std::ops::Range<usize> {
+        let first_move = self
+            .superboard
+            .iter()
+            .flatten()
+            .flatten()
+            .flatten()
+            .all(Option::is_none);
+        match self.sent_to {
+            Some(send) => send..send + 1,
+            None if self.rules.centre_first_move && first_move => Self::CENTRE..Self::CENTRE + 1,
+            None => 0..N * N,
+        }
+    }
+
+    /// Return the legal moves from this state. Will be empty if the game is finished.
+    /// This is a `Vec` rather than a `MoveList`, since there can be up to N⁴ of them.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if self.outcome() != GameOutcome::Ongoing {
+            return vec![];
+        }
+
+        self.playable_boards()
+            .flat_map(|superboard_idx| {
+                let superboard = self.sent_to.is_none().then_some(superboard_idx);
+                self.open_squares(superboard_idx).map(move |square| Move {
+                    superboard,
+                    board: square,
+                })
+            })
+            .collect()
+    }
+
+    /// Apply a move to this state. Panics if the move is invalid; see `try_apply_move`
+    pub fn apply_move(&self, mov: Move) -> Self {
+        self.try_apply_move(mov)
+            .unwrap_or_else(|e| panic!("Illegal move {}: {}", fmt_sized_move(mov, N), e))
+    }
+
+    /// Apply a move to this state, returning an error instead if the move is invalid
+    pub fn try_apply_move(&self, mov: Move) -> Result<Self, MoveError> {
+        let players_ok = (1..=MAX_PLAYERS).contains(&self.num_players);
+        if !players_ok
+            || self.next_to_play >= self.num_players
+            || self.sent_to.is_some_and(|b| b >= N * N)
+        {
+            return Err(MoveError::InvalidState);
+        }
+
+        if self.outcome() != GameOutcome::Ongoing {
+            return Err(MoveError::GameOver);
+        }
+
+        // Check if the superboard move is legal
+        let board_idx = match (self.sent_to, mov.superboard) {
+            (Some(i), None) | (None, Some(i)) => i,
+            (Some(_), Some(_)) => return Err(MoveError::UnexpectedSuperboard),
+            (None, None) => return Err(MoveError::MissingSuperboard),
+        };
+
+        for idx in [board_idx, mov.board] {
+            if idx >= N * N {
+                return Err(MoveError::OutOfRange(idx));
+            }
+        }
+
+        // Check if the board move is legal
+        if !self.playable_boards().contains(&board_idx) {
+            return Err(MoveError::NotPlayable { board: board_idx });
+        }
+        let already_won = at(&self.board_winners, board_idx).is_some();
+        if already_won && !self.rules.play_into_won_boards {
+            return Err(MoveError::BoardWon { board: board_idx });
+        }
+        if at(self.board(board_idx), mov.board).is_some() {
+            return Err(MoveError::Occupied {
+                board: board_idx,
+                square: mov.board,
+            });
+        }
+
+        // Make the move
+        let mut next = *self;
+        let (row, col) = (board_idx / N, board_idx % N);
+        let board = &mut next.superboard[row][col];
+        board[mov.board / N][mov.board % N] = Some(self.next_to_play());
+        // The first player to win a board keeps it
+        if !already_won {
+            next.board_winners[row][col] = is_sized_board_won(board, self.win_len);
+        }
+
+        // Determine if the next player is sent
+        next.sent_to = next
+            .open_squares(mov.board)
+            .next()
+            .is_some()
+            .then_some(mov.board);
+
+        // Calculate the next player
+        next.next_to_play = (self.next_to_play + 1) % self.num_players;
+
+        Ok(next)
+    }
+}
+
+/// Convert a row-major coordinate on an `n`×`n` grid to its character representation, e.g. `D4`.
+/// Panics if `n` is more than `MAX_SIZE`.
+pub fn sized_coord(c: usize, n: usize) -> String {
+    assert!(n <= MAX_SIZE, "Can't write coordinates for n = {}", n);
+    format!("{}{}", ((c % n) as u8 + b'A') as char, c / n + 1)
+}
+
+/// Display a move on an `n`×`n` grid as a string, in the same style as `fmt_move`
+pub fn fmt_sized_move(mov: Move, n: usize) -> String {
+    let mut s = String::new();
+    if let Some(superboard) = mov.superboard {
+        s += &sized_coord(superboard, n);
+        s.push('>');
+    }
+    s += &sized_coord(mov.board, n);
+    s
+}
+
+/// Lay out a superboard as text, optionally showing guides, as `print_superboard` does for N = 3
+pub fn fmt_sized_superboard<const N: usize>(
+    superboard: &SizedSuperBoard<N>,
+    guides: Option<GamePrintGuides>,
+) -> String {
+    fmt_grid(
+        N,
+        |board, square| at(&superboard[board / N][board % N], square),
+        guides,
+    )
+}
+
+/// Lay out an `n`×`n` superboard as text, where `square` gives the owner of a square given the
+/// row-major indices of its sub-board and of the square within it
+pub(crate) fn fmt_grid(
+    n: usize,
+    square: impl Fn(usize, usize) -> Square,
+    guides: Option<GamePrintGuides>,
+) -> String {
+    let letters = |n: usize| (0..n).map(|i| (i as u8 + b'A') as char);
+    let mut s = String::new();
+
+    for superboard_row_idx in 0..n {
+        // Headers for this superboard row, if any
+        s.push(' ');
+        for superboard_column_idx in 0..n {
+            s.push(' ');
+            let superboard_idx = superboard_row_idx * n + superboard_column_idx;
+            match guides {
+                Some(GamePrintGuides::Board(board_idx)) if board_idx == superboard_idx => {
+                    s += &letters(n).map(String::from).collect::<Vec<_>>().join(" ");
+                }
+                Some(GamePrintGuides::Superboard) if superboard_row_idx == 0 => {
+                    let pad = " ".repeat(n - 1);
+                    let letter = letters(n).nth(superboard_column_idx).unwrap();
+                    s += &format!("{}{}{}", pad, letter, pad);
+                }
+                _ => s += &" ".repeat(2 * n - 1),
+            }
+            s.push(' ');
+        }
+        s.push('\n');
+
+        // Board cells
+        for board_row in 0..n {
+            for superboard_column_idx in 0..n {
+                let superboard_idx = superboard_row_idx * n + superboard_column_idx;
+                match guides {
+                    Some(GamePrintGuides::Board(board_idx)) if superboard_idx == board_idx => {
+                        s += &format!(" {}", board_row + 1);
+                    }
+                    Some(GamePrintGuides::Superboard)
+                        if board_row == n / 2 && superboard_column_idx == 0 =>
+                    {
+                        s += &format!("{} ", superboard_row_idx + 1);
+                    }
+                    _ => s += "  ",
+                }
+
+                let row: Vec<String> = (0..n)
+                    .map(|col| square(superboard_idx, board_row * n + col))
+                    .map(|square| (square.unwrap_or(b'-') as char).to_string())
+                    .collect();
+                s += &row.join(" ");
+            }
+            s.push('\n');
+        }
+    }
+
+    s
+}
+
+/// Print a superboard, optionally showing guides
+pub fn print_sized_superboard<const N: usize>(
+    superboard: &SizedSuperBoard<N>,
+    guides: Option<GamePrintGuides>,
+) {
+    print!("{}", fmt_sized_superboard(superboard, guides));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fmt_move, GameState, RuleSet};
+    use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_lines() {
+        assert_eq!(lines(3, 3).count(), 8);
+        // Two lines in each row and column, and four in each diagonal direction
+        assert_eq!(lines(4, 3).count(), 8 + 8 + 4 + 4);
+        assert_eq!(lines(4, 4).count(), 10);
+
+        let mut lines: Vec<Vec<usize>> = lines(3, 3).map(Iterator::collect).collect();
+        lines.sort();
+        let mut expected: Vec<Vec<usize>> = crate::LINES.iter().map(|l| l.to_vec()).collect();
+        expected.sort();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn test_is_sized_board_won() {
+        let board = |rows: [&[u8; 4]; 4]| rows.map(|row| row.map(|c| (c != b'-').then_some(c)));
+
+        let diagonal = board([b"----", b"-X--", b"--X-", b"---X"]);
+        assert_eq!(is_sized_board_won(&diagonal, 3), Some(b'X'));
+        assert_eq!(is_sized_board_won(&diagonal, 4), None);
+
+        let anti_diagonal = board([b"---O", b"--O-", b"-O--", b"X---"]);
+        assert_eq!(is_sized_board_won(&anti_diagonal, 3), Some(b'O'));
+
+        let blocked = board([b"XXOO", b"OOXX", b"XXOO", b"OOXX"]);
+        assert_eq!(is_sized_board_won(&blocked, 3), None);
+        assert!(!sized_board_can_be_won_by(&blocked, 3, b'X'));
+        assert!(sized_board_can_be_won_by(&board([b"XX--"; 4]), 4, b'X'));
+    }
+
+    #[test]
+    fn test_matches_game_state() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let all_rules = (0..8).map(|bits| RuleSet {
+            play_into_won_boards: bits & 1 != 0,
+            ties_count_for_all: bits & 2 != 0,
+            centre_first_move: bits & 4 != 0,
+        });
+        for (rules, players) in all_rules.flat_map(|r| [(r, &b"XO"[..]), (r, b"XOA")]) {
+            for _ in 0..30 {
+                let mut state = GameState::new(players, rules);
+                let mut sized = SizedGameState::<3>::new(players, 3, rules);
+                loop {
+                    assert_eq!(sized.outcome(), state.outcome());
+                    assert_eq!(sized.sent_to, state.sent_to());
+                    for idx in 0..9 {
+                        assert_eq!(sized.board(idx).concat(), state.superboard()[idx]);
+                        assert_eq!(sized.board_result(idx), state.board_result(idx));
+                    }
+
+                    let moves = sized.legal_moves();
+                    assert_eq!(moves, state.legal_moves().to_vec());
+                    let Some(&mov) = moves.choose(&mut rng) else {
+                        break;
+                    };
+                    assert_eq!(fmt_sized_move(mov, 3), fmt_move(mov));
+                    state = state.apply_move(mov);
+                    sized = sized.apply_move(mov);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_four_by_four() {
+        let state = SizedGameState::<4>::new(b"XO", 3, RuleSet::STANDARD);
+        assert_eq!(state.legal_moves().len(), 256);
+
+        let mov = |superboard, board| Move { superboard, board };
+        let state = state.apply_move(mov(Some(15), 5));
+        assert_eq!(state.sent_to, Some(5));
+        assert_eq!(state.legal_moves().len(), 16);
+        assert_eq!(fmt_sized_move(mov(Some(15), 5), 4), "D4>B2");
+
+        assert_eq!(
+            state.try_apply_move(mov(None, 16)),
+            Err(MoveError::OutOfRange(16))
+        );
+        assert_eq!(
+            state.try_apply_move(mov(Some(5), 0)),
+            Err(MoveError::UnexpectedSuperboard)
+        );
+
+        // X takes three in a row on board 0, each time sending O to a board which sends X back
+        let mut state = SizedGameState::<4>::new(b"XO", 3, RuleSet::STANDARD);
+        for square in [5, 6, 7] {
+            assert_eq!(state.board_winners[0][0], None);
+            state = state
+                .apply_move(mov(state.sent_to.is_none().then_some(0), square))
+                .apply_move(mov(None, 0));
+        }
+        assert_eq!(state.board_winners[0][0], Some(b'X'));
+        assert_eq!(state.outcome(), GameOutcome::Ongoing);
+        assert_eq!(
+            state.try_apply_move(mov(Some(0), 8)),
+            Err(MoveError::BoardWon { board: 0 })
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_too_big() {
+        SizedGameState::<10>::new(b"XO", 3, RuleSet::STANDARD);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_centre_first_needs_odd_size() {
+        let rules = RuleSet {
+            centre_first_move: true,
+            ..RuleSet::STANDARD
+        };
+        SizedGameState::<4>::new(b"XO", 3, rules);
+    }
+
+    #[test]
+    fn test_fmt_superboard() {
+        let mut state = SizedGameState::<3>::new(b"XO", 3, RuleSet::STANDARD);
+        state = state.apply_move(Move {
+            superboard: Some(0),
+            board: 4,
+        });
+        let expected = [
+            "    A      B      C",
+            "  - - -  - - -  - - -",
+            "1 - X -  - - -  - - -",
+            "  - - -  - - -  - - -",
+            "",
+            "  - - -  - - -  - - -",
+            "2 - - -  - - -  - - -",
+            "  - - -  - - -  - - -",
+            "",
+            "  - - -  - - -  - - -",
+            "3 - - -  - - -  - - -",
+            "  - - -  - - -  - - -",
+        ];
+        let text = fmt_sized_superboard(&state.superboard, Some(GamePrintGuides::Superboard));
+        assert_eq!(
+            text.lines().map(str::trim_end).collect::<Vec<_>>(),
+            expected
+        );
+
+        let state = SizedGameState::<4>::new(b"XO", 3, RuleSet::STANDARD);
+        let text = fmt_sized_superboard(&state.superboard, Some(GamePrintGuides::Board(1)));
+        let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+        assert_eq!(lines[0], "           A B C D");
+        assert_eq!(lines[2], "  - - - - 2- - - -  - - - -  - - - -");
+    }
+}
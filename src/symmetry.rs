@@ -0,0 +1,210 @@
+//! The eight symmetries of the square (rotations and reflections), applied to whole game states.
+//! A symmetry moves the sub-boards around the superboard and the squares around every sub-board
+//! in the same way, and moves `sent_to` along with them, so the transformed state plays out
+//! exactly like the original.
+//!
+//! `canonical_state` picks one representative of each set of symmetric states, so searches and
+//! tables can store a position once rather than up to eight times.
+
+use crate::{
+    bitboard::{BitGameState, BoardMask},
+    GameState, Move,
+};
+
+/// Index of each symmetry's source square: a transformed board has `board[SYMMETRIES[s][i]]` at
+/// square `i`. In the same order as `single_board_solve::invariant_boards`.
+pub const SYMMETRIES: [[usize; 9]; 8] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8], // Identity
+    [6, 3, 0, 7, 4, 1, 8, 5, 2], // Rotate clockwise
+    [8, 7, 6, 5, 4, 3, 2, 1, 0], // Rotate half a turn
+    [2, 5, 8, 1, 4, 7, 0, 3, 6], // Rotate counterclockwise
+    [2, 1, 0, 5, 4, 3, 8, 7, 6], // Horizontal flip
+    [8, 5, 2, 7, 4, 1, 6, 3, 0], // Flip about the anti-diagonal
+    [6, 7, 8, 3, 4, 5, 0, 1, 2], // Vertical flip
+    [0, 3, 6, 1, 4, 7, 2, 5, 8], // Flip about the diagonal
+];
+
+/// `MASK_SYMMETRIES[sym][mask]` is a board mask (or mask of sub-boards) with symmetry `sym` applied
+pub static MASK_SYMMETRIES: [[BoardMask; 512]; 8] = build_mask_symmetries();
+
+const fn build_mask_symmetries() -> [[BoardMask; 512]; 8] {
+    let mut table = [[0; 512]; 8];
+    let mut sym = 0;
+    while sym < 8 {
+        let mut mask = 0;
+        while mask < 512 {
+            let mut square = 0;
+            while square < 9 {
+                if mask & (1 << SYMMETRIES[sym][square]) != 0 {
+                    table[sym][mask] |= 1 << square;
+                }
+                square += 1;
+            }
+            mask += 1;
+        }
+        sym += 1;
+    }
+    table
+}
+
+/// The symmetry which undoes each symmetry
+pub const INVERSES: [usize; 8] = [0, 3, 2, 1, 4, 5, 6, 7];
+
+/// Where symmetry `sym` moves the square (or sub-board) at `idx`
+pub fn map_index(sym: usize, idx: usize) -> usize {
+    SYMMETRIES[INVERSES[sym]][idx]
+}
+
+/// Apply a symmetry to a move
+pub fn transform_move(sym: usize, mov: Move) -> Move {
+    Move {
+        superboard: mov.superboard.map(|idx| map_index(sym, idx)),
+        board: map_index(sym, mov.board),
+    }
+}
+
+impl GameState {
+    /// Apply the symmetry with index `sym` (see `SYMMETRIES`) to the whole state
+    pub fn transform(&self, sym: usize) -> GameState {
+        let source = SYMMETRIES[sym];
+        GameState {
+            superboard: source.map(|board| source.map(|square| self.superboard[board][square])),
+            board_winners: source.map(|board| self.board_winners[board]),
+            sent_to: self.sent_to.map(|board| map_index(sym, board)),
+            ..*self
+        }
+    }
+
+    /// This state under each of the eight symmetries, starting with itself
+    pub fn symmetries(&self) -> [GameState; 8] {
+        std::array::from_fn(|sym| self.transform(sym))
+    }
+
+    /// The representative of this state's symmetries which every one of them maps to,
+    /// along with the symmetry which takes this state there. Picked the same way as
+    /// `BitGameState::canonical_symmetry`, so both engines agree on it.
+    pub fn canonical_symmetry(&self) -> (GameState, usize) {
+        let (bits, sym) = BitGameState::from(self).canonical_symmetry();
+        (GameState::from(bits), sym)
+    }
+
+    /// The representative of this state's symmetries. Symmetric states share a canonical state.
+    pub fn canonical_state(&self) -> GameState {
+        self.canonical_symmetry().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{single_board_solve::invariant_boards, successors, RuleSet};
+    use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_matches_invariant_boards() {
+        let board = std::array::from_fn(|i| Some(i as u8));
+        let boards = invariant_boards(board);
+        for (sym, source) in SYMMETRIES.iter().enumerate() {
+            assert_eq!(boards[sym], source.map(|i| Some(i as u8)));
+            for idx in 0..9 {
+                assert_eq!(map_index(INVERSES[sym], map_index(sym, idx)), idx);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transform_matches_arrays() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut state = GameState::new(b"XOA", RuleSet::STANDARD);
+        while let Some(&mov) = state.legal_moves().choose(&mut rng) {
+            state = state.apply_move(mov);
+            for (sym, source) in SYMMETRIES.iter().enumerate() {
+                let transformed = state.transform(sym);
+                let superboard = state.superboard();
+                assert_eq!(
+                    *transformed.superboard(),
+                    source.map(|board| source.map(|square| superboard[board][square]))
+                );
+                for board in 0..9 {
+                    assert_eq!(
+                        transformed.board_winner(map_index(sym, board)),
+                        state.board_winner(board)
+                    );
+                }
+                assert_eq!(
+                    transformed.sent_to(),
+                    state.sent_to().map(|b| map_index(sym, b))
+                );
+                assert_eq!(transformed.key(), transformed.compute_key());
+            }
+        }
+    }
+
+    #[test]
+    fn test_successors_commute() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let rules = RuleSet {
+            play_into_won_boards: true,
+            ..RuleSet::STANDARD
+        };
+        for rules in [RuleSet::STANDARD, rules] {
+            for _ in 0..10 {
+                let mut state = GameState::new(b"XO", rules);
+                loop {
+                    for (sym, transformed) in state.symmetries().into_iter().enumerate() {
+                        assert_eq!(transformed.outcome(), state.outcome());
+
+                        let mut expected: Vec<Move> = successors(&state)
+                            .into_iter()
+                            .map(|mov| transform_move(sym, mov))
+                            .collect();
+                        let mut moves = successors(&transformed);
+                        expected.sort_by_key(|mov| (mov.superboard, mov.board));
+                        moves.sort_by_key(|mov| (mov.superboard, mov.board));
+                        assert_eq!(moves, expected);
+
+                        for mov in successors(&state) {
+                            assert_eq!(
+                                state.apply_move(mov).transform(sym),
+                                transformed.apply_move(transform_move(sym, mov))
+                            );
+                        }
+                    }
+
+                    let moves = state.legal_moves();
+                    let Some(&mov) = moves.choose(&mut rng) else {
+                        break;
+                    };
+                    state = state.apply_move(mov);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonical_state() {
+        let state = GameState::new(b"XO", RuleSet::default());
+        let mov = |superboard, board| Move { superboard, board };
+
+        // Only 15 of the 81 opening moves differ up to symmetry
+        let openings: Vec<GameState> = successors(&state)
+            .into_iter()
+            .map(|m| state.apply_move(m).canonical_state())
+            .collect();
+        let unique: std::collections::HashSet<GameState> = openings.into_iter().collect();
+        assert_eq!(unique.len(), 15);
+
+        let corner = state.apply_move(mov(Some(0), 0));
+        for transformed in corner.symmetries() {
+            assert_eq!(transformed.canonical_state(), corner.canonical_state());
+            let (canonical, sym) = transformed.canonical_symmetry();
+            assert_eq!(transformed.transform(sym), canonical);
+        }
+
+        let bits = BitGameState::from(&corner.transform(1));
+        assert_eq!(
+            bits.canonical_state().to_game_state(),
+            corner.canonical_state()
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Zobrist keys for game states. Every feature of a state (a player on a square, a player
+//! owning a sub-board, the board the player to move was sent to, and whose turn it is) has a
+//! random 64-bit key, and a state's key is the XOR of the keys of its features.
+//! `BitGameState::apply_move` updates its key a feature at a time, so looking a state up costs
+//! nothing extra. `GameState` has public fields, so it works its key out from them when asked.
+//!
+//! `GameState` and `BitGameState` hash as just their key, and `ZobristMap` uses it as the hash
+//! directly instead of running it through SipHash.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{BuildHasherDefault, Hasher},
+};
+
+use crate::{GameState, MAX_PLAYERS};
+
+/// Random keys for each feature, by player index rather than symbol
+pub struct Keys {
+    /// `cells[player][9 * board + square]`
+    pub cells: [[u64; 81]; MAX_PLAYERS],
+    /// `winners[player][board]`, for sub-boards won by that player
+    pub winners: [[u64; 9]; MAX_PLAYERS],
+    /// The sub-board the player to move was sent to
+    pub sent_to: [u64; 9],
+    /// The player to move
+    pub to_move: [u64; MAX_PLAYERS],
+}
+
+/// The keys used by every state, generated at compile time
+pub static KEYS: Keys = build_keys();
+
+/// One step of the SplitMix64 generator, returning the new state and its output
+const fn split_mix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn build_keys() -> Keys {
+    let mut keys = Keys {
+        cells: [[0; 81]; MAX_PLAYERS],
+        winners: [[0; 9]; MAX_PLAYERS],
+        sent_to: [0; 9],
+        to_move: [0; MAX_PLAYERS],
+    };
+    let mut seed = 0x5EED;
+    let mut player = 0;
+    while player < MAX_PLAYERS {
+        let mut i = 0;
+        while i < 81 {
+            let (next, key) = split_mix(seed);
+            (seed, keys.cells[player][i]) = (next, key);
+            i += 1;
+        }
+        let mut board = 0;
+        while board < 9 {
+            let (next, key) = split_mix(seed);
+            (seed, keys.winners[player][board]) = (next, key);
+            board += 1;
+        }
+        let (next, key) = split_mix(seed);
+        (seed, keys.to_move[player]) = (next, key);
+        player += 1;
+    }
+    let mut board = 0;
+    while board < 9 {
+        let (next, key) = split_mix(seed);
+        (seed, keys.sent_to[board]) = (next, key);
+        board += 1;
+    }
+    keys
+}
+
+/// Key for the player at `player_idx` holding a square
+pub fn cell_key(player_idx: usize, board: usize, square: usize) -> u64 {
+    KEYS.cells[player_idx][9 * board + square]
+}
+
+/// Key for the player at `player_idx` owning a sub-board
+pub fn winner_key(player_idx: usize, board: usize) -> u64 {
+    KEYS.winners[player_idx][board]
+}
+
+/// Key for the sub-board the player to move was sent to, if any
+pub fn sent_to_key(sent_to: Option<usize>) -> u64 {
+    sent_to.map_or(0, |board| KEYS.sent_to[board])
+}
+
+/// Key for the player at `player_idx` being next to move
+pub fn to_move_key(player_idx: usize) -> u64 {
+    KEYS.to_move[player_idx]
+}
+
+impl GameState {
+    /// Work out this state's key from scratch, rather than move by move
+    pub fn compute_key(&self) -> u64 {
+        let index_of = |symbol| self.players().iter().position(|&p| p == symbol);
+        let mut key = to_move_key(self.next_to_play_idx()) ^ sent_to_key(self.sent_to());
+        for (board_idx, board) in self.superboard().iter().enumerate() {
+            for (square, cell) in board.iter().enumerate() {
+                if let Some(p) = cell.and_then(index_of) {
+                    key ^= cell_key(p, board_idx, square);
+                }
+            }
+            if let Some(p) = self.board_winner(board_idx).and_then(index_of) {
+                key ^= winner_key(p, board_idx);
+            }
+        }
+        key
+    }
+}
+
+/// A hasher which passes Zobrist keys straight through. Anything else is hashed with FNV-1a.
+#[derive(Copy, Clone, Debug)]
+pub struct ZobristHasher(u64);
+
+impl Default for ZobristHasher {
+    fn default() -> Self {
+        Self(0xCBF29CE484222325)
+    }
+}
+
+impl Hasher for ZobristHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001B3);
+        }
+    }
+
+    fn write_u64(&mut self, key: u64) {
+        self.0 ^= key;
+    }
+}
+
+/// A `HashMap` keyed by game states, hashed by their Zobrist keys
+pub type ZobristMap<K, V> = HashMap<K, V, BuildHasherDefault<ZobristHasher>>;
+
+/// A `HashSet` of game states, hashed by their Zobrist keys
+pub type ZobristSet<K> = HashSet<K, BuildHasherDefault<ZobristHasher>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitboard::BitGameState, Move, RuleSet};
+    use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_keys_are_distinct() {
+        let mut all: Vec<u64> = KEYS.cells.iter().flatten().copied().collect();
+        all.extend(KEYS.winners.iter().flatten());
+        all.extend(KEYS.sent_to);
+        all.extend(KEYS.to_move);
+        let count = all.len();
+        all.sort_unstable();
+        all.dedup();
+        assert_eq!(all.len(), count);
+    }
+
+    #[test]
+    fn test_incremental_keys() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let rules = RuleSet {
+            play_into_won_boards: true,
+            ..RuleSet::STANDARD
+        };
+        for rules in [RuleSet::STANDARD, rules] {
+            for players in [&b"XO"[..], b"XOAB"] {
+                let mut state = GameState::new(players, rules);
+                let mut bits = BitGameState::from(&state);
+                loop {
+                    assert_eq!(state.key(), state.compute_key());
+                    assert_eq!(bits.key(), state.key());
+                    let moves = state.legal_moves();
+                    let Some(&mov) = moves.choose(&mut rng) else {
+                        break;
+                    };
+                    state = state.apply_move(mov);
+                    bits = bits.apply_move(mov);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_position_same_key() {
+        let mov = |superboard, board| Move { superboard, board };
+        let played = GameState::new(b"XO", RuleSet::default())
+            .apply_move(mov(Some(4), 0))
+            .apply_move(mov(None, 8))
+            .apply_move(mov(None, 4));
+
+        // The same position, set up by hand
+        let mut superboard = crate::EMPTY_SUPERBOARD;
+        superboard[4][0] = Some(b'X');
+        superboard[0][8] = Some(b'O');
+        superboard[8][4] = Some(b'X');
+        let set_up = GameState::new(b"XO", RuleSet::default())
+            .with_superboard(superboard)
+            .with_next_to_play(1)
+            .with_sent_to(Some(4));
+        assert_eq!(set_up, played);
+        assert_eq!(set_up.key(), played.key());
+
+        // Or by setting the fields directly
+        let by_fields = GameState {
+            superboard,
+            next_to_play: 1,
+            sent_to: Some(4),
+            ..GameState::new(b"XO", RuleSet::default())
+        };
+        assert_eq!(by_fields, played);
+        assert_eq!(by_fields.key(), played.key());
+
+        let mut map = ZobristMap::default();
+        map.insert(played, 1);
+        assert_eq!(map.get(&set_up), Some(&1));
+        assert_eq!(map.get(&GameState::new(b"XO", RuleSet::default())), None);
+    }
+}